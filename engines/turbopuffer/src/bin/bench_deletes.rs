@@ -0,0 +1,167 @@
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use turbopuffer_bench::client::{Access, AggregationResponse, ApiKeys, Client, ClientArgs};
+use turbopuffer_bench::corpus::{self, Document};
+use turbopuffer_bench::otlp;
+use turbopuffer_bench::query::Consistency;
+use turbopuffer_bench::stats::LatencyHistogram;
+
+/// Deletes a fraction of a namespace built by `build_index`, in batches, and reports the delete
+/// throughput and how long queries keep returning the deleted documents after the last delete.
+///
+/// The namespace is left without the deleted documents: run this against a copy built for the
+/// purpose, e.g. with `--namespace`.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    client: ClientArgs,
+
+    /// How the documents are selected.
+    #[arg(long, value_enum, default_value = "ids")]
+    by: DeleteBy,
+
+    /// Fraction of the documents deleted.
+    #[arg(long, default_value_t = 0.01, value_parser = corpus::parse_fraction)]
+    fraction: f64,
+
+    /// Number of ids per delete request, with `--by ids`.
+    #[arg(long, default_value_t = 1_000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// Number of delete requests, with `--by filter`.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    batches: u64,
+
+    /// Maximum number of attempts per request.
+    #[arg(long, default_value_t = 8)]
+    max_attempts: u32,
+
+    /// Consistency level of the queries checking whether deleted documents are still returned.
+    #[arg(long, value_enum, default_value = "eventual")]
+    consistency: Consistency,
+
+    /// Milliseconds between two queries checking whether deleted documents are still returned.
+    #[arg(long, default_value_t = 100)]
+    poll_interval_ms: u64,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum DeleteBy {
+    /// Delete documents by id, sampled by hash of their id from the corpus read from stdin.
+    Ids,
+    /// Delete documents by a filter on the synthetic timestamp attribute, one window of
+    /// timestamps per request, in a namespace built with `--synthetic-attributes`. Nothing is
+    /// read from stdin.
+    Filter,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let api_keys = ApiKeys::from_env(Access::ReadWrite)?;
+    let client = Client::new(&args.client, &api_keys, args.max_attempts)?;
+
+    let ids = match args.by {
+        DeleteBy::Ids => sample_ids(args.fraction)?,
+        DeleteBy::Filter => vec![],
+    };
+
+    let mut latencies = LatencyHistogram::default();
+    let mut deleted = 0;
+    let start = Instant::now();
+    // Matches the documents deleted by the last request.
+    let last_deleted = match args.by {
+        DeleteBy::Ids => {
+            for batch in ids.chunks(args.batch_size as usize) {
+                let batch = batch.iter().map(String::as_str).collect::<Vec<_>>();
+                let request_start = Instant::now();
+                deleted += client.delete_rows(&batch).await?.body;
+                latencies.record(request_start.elapsed());
+            }
+            let last = ids
+                .chunks(args.batch_size as usize)
+                .last()
+                .unwrap_or_default();
+            serde_json::json!(["id", "In", last])
+        }
+        DeleteBy::Filter => {
+            let end = (corpus::TIMESTAMP_RANGE as f64 * args.fraction).round() as u64;
+            let mut window = serde_json::Value::Null;
+            for batch in 0..args.batches {
+                let (from, to) = (end * batch / args.batches, end * (batch + 1) / args.batches);
+                window = serde_json::json!([
+                    "And",
+                    [
+                        [corpus::TIMESTAMP_ATTRIBUTE, "Gte", from],
+                        [corpus::TIMESTAMP_ATTRIBUTE, "Lt", to],
+                    ]
+                ]);
+                let request_start = Instant::now();
+                deleted += client.delete_by_filter(&window).await?.body;
+                latencies.record(request_start.elapsed());
+            }
+            if deleted == 0 {
+                anyhow::bail!(
+                    "no document has a timestamp, build the namespace with --synthetic-attributes"
+                );
+            }
+            window
+        }
+    };
+    let elapsed = start.elapsed();
+    println!(
+        "{deleted} documents deleted in {elapsed:?} ({:.0} docs/s)",
+        deleted as f64 / elapsed.as_secs_f64()
+    );
+    println!("delete latency: {}", latencies.summary());
+
+    let lag = wait_until_gone(&client, &last_deleted, &args).await?;
+    println!("deleted documents no longer returned by queries {lag:?} after the last delete");
+    otlp::flush().await;
+    Ok(())
+}
+
+/// Reads the corpus from stdin and returns the ids of a `fraction` of the documents.
+fn sample_ids(fraction: f64) -> Result<Vec<String>, anyhow::Error> {
+    let mut ids = vec![];
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let doc: Document = serde_json::from_str(&line)?;
+        if corpus::sampled(&doc.id, fraction) {
+            ids.push(doc.id);
+        }
+    }
+    if ids.is_empty() {
+        anyhow::bail!("no document sampled, increase --fraction");
+    }
+    println!("deleting {} documents by id", ids.len());
+    Ok(ids)
+}
+
+/// Counts the documents matching `filter` until there are none, returning how long it took.
+async fn wait_until_gone(
+    client: &Client,
+    filter: &serde_json::Value,
+    args: &Args,
+) -> Result<Duration, anyhow::Error> {
+    let body = serde_json::json!({
+        "aggregate_by": {
+            "count": ["Count"],
+        },
+        "filters": filter,
+        "consistency": {"level": args.consistency.level()},
+    });
+    let start = Instant::now();
+    loop {
+        let response = client.query::<AggregationResponse>(&body).await?;
+        if response.body.aggregations["count"] == 0 {
+            return Ok(start.elapsed());
+        }
+        tokio::time::sleep(Duration::from_millis(args.poll_interval_ms)).await;
+    }
+}
@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use clap::Parser;
+use turbopuffer_bench::client::{Access, ApiKeys, Client, ClientArgs, QueryResponse};
+use turbopuffer_bench::otlp;
+use turbopuffer_bench::query::Consistency;
+use turbopuffer_bench::stats::LatencyHistogram;
+
+/// Upserts one document at a time, each with a term found in no other document, and queries
+/// for that term right after the upsert is acknowledged until the document is returned.
+/// Reports the distribution of the time from the acknowledgement to the document being
+/// visible, the read-your-writes latency.
+///
+/// The documents are deleted at the end, unless `--keep`.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    client: ClientArgs,
+
+    /// Number of documents upserted and waited for, one after the other.
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    samples: u64,
+
+    /// Maximum number of attempts per request.
+    #[arg(long, default_value_t = 8)]
+    max_attempts: u32,
+
+    /// Consistency level of the queries looking for the upserted document.
+    #[arg(long, value_enum, default_value = "strong")]
+    consistency: Consistency,
+
+    /// Milliseconds between two queries looking for the upserted document.
+    #[arg(long, default_value_t = 0)]
+    poll_interval_ms: u64,
+
+    /// Give up on a document that isn't visible after this many seconds.
+    #[arg(long, default_value_t = 60.0)]
+    timeout_secs: f64,
+
+    /// Leave the upserted documents in the namespace.
+    #[arg(long)]
+    keep: bool,
+
+    /// Write the visibility latency histogram to this JSON file on exit.
+    #[arg(long)]
+    latency_histogram: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let api_keys = ApiKeys::from_env(Access::ReadWrite)?;
+    if !(args.timeout_secs > 0.0 && args.timeout_secs.is_finite()) {
+        anyhow::bail!("--timeout-secs must be positive");
+    }
+    let timeout = Duration::from_secs_f64(args.timeout_secs);
+    let client = Client::new(&args.client, &api_keys, args.max_attempts)?;
+
+    // Distinct across runs, so that documents left by an earlier run with `--keep` don't match.
+    let run = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_nanos() as u64;
+    let mut ids = vec![];
+    let mut upsert_latencies = LatencyHistogram::default();
+    let mut visibility = LatencyHistogram::default();
+    let mut polls = 0;
+    for sample in 0..args.samples {
+        let id = format!("freshness-{run:x}-{sample}");
+        // Letters and digits only, so that the tokenizer keeps it as one term.
+        let term = format!("freshness{run:x}x{sample}");
+        let row = serde_json::json!({ "id": id, "text": format!("read your writes {term}") });
+        let request_start = Instant::now();
+        client.upsert_rows(&[row], None, None).await?;
+        upsert_latencies.record(request_start.elapsed());
+
+        let body = serde_json::json!({
+            "rank_by": ["text", "BM25", term],
+            "top_k": 1,
+            "consistency": {"level": args.consistency.level()},
+        });
+        let acknowledged = Instant::now();
+        loop {
+            let response = client.query::<QueryResponse>(&body).await?;
+            polls += 1;
+            if !response.body.rows.is_empty() {
+                visibility.record(acknowledged.elapsed());
+                break;
+            }
+            if acknowledged.elapsed() > timeout {
+                anyhow::bail!("document {id} not visible after {timeout:?}");
+            }
+            tokio::time::sleep(Duration::from_millis(args.poll_interval_ms)).await;
+        }
+        ids.push(id);
+    }
+    println!("upsert latency: {}", upsert_latencies.summary());
+    println!(
+        "visible after: {} ({:.1} queries per document)",
+        visibility.summary(),
+        polls as f64 / args.samples as f64
+    );
+    if let Some(path) = &args.latency_histogram {
+        visibility.write_json(path)?;
+    }
+
+    if !args.keep {
+        let ids = ids.iter().map(String::as_str).collect::<Vec<_>>();
+        client.delete_rows(&ids).await?;
+    }
+    otlp::flush().await;
+    Ok(())
+}
@@ -0,0 +1,320 @@
+use std::fmt::Write;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use tokio::task::JoinSet;
+use turbopuffer_bench::client::{
+    Access, AggregationResponse, ApiKeys, Client, ClientArgs, QueryResponse,
+};
+use turbopuffer_bench::corpus::{self, Document};
+use turbopuffer_bench::otlp;
+use turbopuffer_bench::query::{self, Consistency, ParseOptions, Query};
+use turbopuffer_bench::stats::LatencyHistogram;
+
+/// Runs queries from `--queries` and re-upserts of a sample of the corpus read from stdin
+/// concurrently, in a fixed ratio, against a namespace built by `build_index` from the same
+/// corpus. Reports the query latency of a query-only baseline and how it degrades under the
+/// write load.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    client: ClientArgs,
+
+    /// File with `<COMMAND>\t<query>` lines, in the format of the query file of the benchmark.
+    /// Unsupported commands are skipped, as are hybrid, paged and grouped ones.
+    #[arg(long)]
+    queries: PathBuf,
+
+    /// Fraction of the operations that are upserts, e.g. 0.05 for 95% queries and 5% upserts.
+    #[arg(long, default_value_t = 0.05, value_parser = parse_write_fraction)]
+    write_fraction: f64,
+
+    /// Seconds the mixed workload runs for.
+    #[arg(long, default_value_t = 60)]
+    duration_secs: u64,
+
+    /// Seconds the query-only baseline runs for before the mixed workload, 0 to skip it.
+    #[arg(long, default_value_t = 60)]
+    baseline_secs: u64,
+
+    /// Number of operations in flight.
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: u16,
+
+    /// Fraction of the corpus re-upserted by the writes, chosen by hash of the document id.
+    #[arg(long, default_value_t = 0.01, value_parser = corpus::parse_fraction)]
+    fraction: f64,
+
+    /// Number of documents per upsert request.
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// Maximum number of attempts per request.
+    #[arg(long, default_value_t = 8)]
+    max_attempts: u32,
+
+    /// Consistency level of the queries.
+    #[arg(long, value_enum, default_value = "eventual")]
+    consistency: Consistency,
+
+    /// The namespace was built with `--synthetic-filters`.
+    #[arg(long)]
+    synthetic_filters: bool,
+
+    /// The namespace was built with `--synthetic-attributes`.
+    #[arg(long)]
+    synthetic_attributes: bool,
+
+    /// The `--filter-selectivities` the namespace was built with.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = corpus::FILTER_SELECTIVITIES,
+        value_parser = corpus::parse_selectivity
+    )]
+    filter_selectivities: Vec<f64>,
+
+    /// The `--vector-field` the namespace was built with, if any.
+    #[arg(long)]
+    vector_field: Option<String>,
+
+    /// The `--distance-metric` the namespace was built with, if it has vectors.
+    #[arg(long, default_value = "cosine_distance")]
+    distance_metric: String,
+}
+
+/// What the workers of a phase share.
+struct Workload {
+    client: Client,
+    queries: Vec<Query>,
+    sample: Vec<Document>,
+    batch_size: usize,
+    /// The selectivities of the synthetic `filter` tags, with `--synthetic-filters`.
+    synthetic_filters: Option<Vec<f64>>,
+    /// Whether the documents get the synthetic attributes, with `--synthetic-attributes`.
+    synthetic_attributes: bool,
+    distance_metric: Option<String>,
+    /// Number of upsert requests sent so far, appended to the text of the upserted documents so
+    /// that every upsert changes them.
+    revision: AtomicU64,
+}
+
+/// What the workers of a phase measured.
+#[derive(Default)]
+struct PhaseStats {
+    queries: LatencyHistogram,
+    upserts: LatencyHistogram,
+    upserted_docs: u64,
+    exhaustive_queries: usize,
+}
+
+impl PhaseStats {
+    fn merge(&mut self, other: PhaseStats) {
+        self.queries.merge(&other.queries);
+        self.upserts.merge(&other.upserts);
+        self.upserted_docs += other.upserted_docs;
+        self.exhaustive_queries += other.exhaustive_queries;
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let api_keys = ApiKeys::from_env(Access::ReadWrite)?;
+    let client = Client::new(&args.client, &api_keys, args.max_attempts)?;
+    let options = ParseOptions {
+        consistency: args.consistency,
+        filter_selectivities: args.filter_selectivities.clone(),
+        synthetic_attributes: args.synthetic_attributes,
+        ..ParseOptions::default()
+    };
+    let (queries, skipped) = query::load_single_request_queries(&args.queries, &options)?;
+    println!("loaded {} queries, skipped {skipped}", queries.len());
+    let sample = if args.write_fraction > 0.0 {
+        sample_documents(&args)?
+    } else {
+        vec![]
+    };
+    let workload = Arc::new(Workload {
+        client,
+        queries,
+        sample,
+        batch_size: args.batch_size as usize,
+        synthetic_filters: args
+            .synthetic_filters
+            .then(|| args.filter_selectivities.clone()),
+        synthetic_attributes: args.synthetic_attributes,
+        distance_metric: args
+            .vector_field
+            .is_some()
+            .then(|| args.distance_metric.clone()),
+        revision: AtomicU64::new(0),
+    });
+
+    let baseline = if args.baseline_secs > 0 {
+        let duration = Duration::from_secs(args.baseline_secs);
+        let baseline = run_phase(&workload, args.concurrency, 0.0, duration).await?;
+        println!(
+            "baseline: {:.1} queries/s, query latency {}",
+            baseline.queries.len() as f64 / duration.as_secs_f64(),
+            baseline.queries.summary()
+        );
+        Some(baseline)
+    } else {
+        None
+    };
+
+    let duration = Duration::from_secs(args.duration_secs);
+    let mixed = run_phase(&workload, args.concurrency, args.write_fraction, duration).await?;
+    println!(
+        "mixed: {:.1} queries/s, query latency {}",
+        mixed.queries.len() as f64 / duration.as_secs_f64(),
+        mixed.queries.summary()
+    );
+    println!(
+        "mixed: {:.0} docs/s upserted, upsert latency {}",
+        mixed.upserted_docs as f64 / duration.as_secs_f64(),
+        mixed.upserts.summary()
+    );
+    if mixed.exhaustive_queries > 0 {
+        println!(
+            "{} queries searched unindexed documents exhaustively under write load",
+            mixed.exhaustive_queries
+        );
+    }
+    let baseline = baseline.filter(|baseline| !baseline.queries.is_empty());
+    if let (Some(baseline), false) = (baseline, mixed.queries.is_empty()) {
+        let ratio = |percentile| {
+            mixed.queries.percentile(percentile).as_secs_f64()
+                / baseline.queries.percentile(percentile).as_secs_f64()
+        };
+        println!(
+            "query latency under write load: p50 x{:.2} p90 x{:.2} p99 x{:.2}",
+            ratio(50.0),
+            ratio(90.0),
+            ratio(99.0)
+        );
+    }
+    otlp::flush().await;
+    Ok(())
+}
+
+/// Reads the corpus from stdin and returns a `--fraction` of the documents.
+fn sample_documents(args: &Args) -> Result<Vec<Document>, anyhow::Error> {
+    let mut sample = vec![];
+    let mut read = 0;
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        read += 1;
+        let mut doc: Document = serde_json::from_str(&line)?;
+        if !corpus::sampled(&doc.id, args.fraction) {
+            continue;
+        }
+        if let Some(field) = &args.vector_field {
+            let Some(vector) = doc.extra.remove(field) else {
+                anyhow::bail!("document {:?} has no {field:?} field", doc.id);
+            };
+            doc.vector = Some(serde_json::from_value(vector)?);
+        }
+        sample.push(doc);
+    }
+    if sample.is_empty() {
+        anyhow::bail!("no document sampled out of {read}, increase --fraction");
+    }
+    println!("sampled {} of {read} documents", sample.len());
+    Ok(sample)
+}
+
+/// Runs `concurrency` workers for `duration`, each sending one operation at a time: an upsert
+/// with probability `write_fraction`, a random query otherwise.
+async fn run_phase(
+    workload: &Arc<Workload>,
+    concurrency: u16,
+    write_fraction: f64,
+    duration: Duration,
+) -> Result<PhaseStats, anyhow::Error> {
+    let deadline = Instant::now() + duration;
+    let mut workers = JoinSet::new();
+    for _ in 0..concurrency {
+        let workload = workload.clone();
+        workers.spawn(async move { work(&workload, write_fraction, deadline).await });
+    }
+    let mut stats = PhaseStats::default();
+    while let Some(joined) = workers.join_next().await {
+        stats.merge(joined??);
+    }
+    Ok(stats)
+}
+
+async fn work(
+    workload: &Workload,
+    write_fraction: f64,
+    deadline: Instant,
+) -> Result<PhaseStats, anyhow::Error> {
+    let mut stats = PhaseStats::default();
+    while Instant::now() < deadline {
+        if rand::random::<f64>() < write_fraction {
+            let batch = upsert_batch(workload)?;
+            let start = Instant::now();
+            let distance_metric = workload.distance_metric.as_deref();
+            let upserted = workload
+                .client
+                .upsert_rows(&batch, None, distance_metric)
+                .await?;
+            stats.upserts.record(start.elapsed());
+            stats.upserted_docs += upserted.body.rows_affected;
+        } else {
+            let query = &workload.queries[rand::random_range(0..workload.queries.len())];
+            let start = Instant::now();
+            let performance = if query.count_only {
+                let response = workload
+                    .client
+                    .query::<AggregationResponse>(&query.body)
+                    .await?;
+                response.body.performance
+            } else {
+                let response = workload.client.query::<QueryResponse>(&query.body).await?;
+                response.body.performance
+            };
+            stats.queries.record(start.elapsed());
+            if performance.exhaustive_search_count > 0 {
+                stats.exhaustive_queries += 1;
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// Picks a random batch of the sample and changes the text of its documents.
+fn upsert_batch(workload: &Workload) -> Result<Vec<Document>, anyhow::Error> {
+    let revision = workload.revision.fetch_add(1, Ordering::Relaxed) + 1;
+    let sample = &workload.sample;
+    let batches = sample.len().div_ceil(workload.batch_size);
+    let start = rand::random_range(0..batches) * workload.batch_size;
+    let end = (start + workload.batch_size).min(sample.len());
+    let mut batch = sample[start..end].to_vec();
+    for doc in &mut batch {
+        write!(doc.text, " revision{revision}")?;
+        corpus::augment(
+            doc,
+            workload.synthetic_filters.as_deref(),
+            workload.synthetic_attributes,
+        );
+    }
+    Ok(batch)
+}
+
+fn parse_write_fraction(value: &str) -> Result<f64, anyhow::Error> {
+    let fraction: f64 = value.parse()?;
+    if !(0.0..1.0).contains(&fraction) {
+        anyhow::bail!("write fraction must be in [0, 1)");
+    }
+    Ok(fraction)
+}
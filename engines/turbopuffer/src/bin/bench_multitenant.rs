@@ -0,0 +1,381 @@
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use tokio::task::JoinSet;
+use turbopuffer_bench::client::{
+    Access, AggregationResponse, ApiKeys, Client, ClientArgs, QueryResponse,
+};
+use turbopuffer_bench::corpus::{self, Document};
+use turbopuffer_bench::otlp;
+use turbopuffer_bench::query::{self, Consistency, ParseOptions, Query};
+use turbopuffer_bench::random::SplitMix64;
+use turbopuffer_bench::stats::LatencyHistogram;
+
+/// Splits the corpus read from stdin into `--tenants` small namespaces, `<namespace>-t<i>`, and
+/// runs the queries of `--queries` against tenants picked uniformly or following a Zipf
+/// distribution, like a service with one namespace per customer. Reports the latency of the
+/// first query of every tenant, which finds its namespace cold, that of the other queries, and
+/// the aggregate throughput. Queries start once every tenant is indexed, so that the first ones
+/// measure cold caches rather than an exhaustive search of unindexed documents.
+///
+/// The tenant namespaces are deleted at the end, unless `--keep`.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    client: ClientArgs,
+
+    /// Number of tenant namespaces. Every document goes to one of them, by hash of its id.
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    tenants: u64,
+
+    /// File with `<COMMAND>\t<query>` lines, in the format of the query file of the benchmark.
+    /// Unsupported commands are skipped, as are hybrid, paged and grouped ones.
+    #[arg(long)]
+    queries: PathBuf,
+
+    /// How the tenant of every query is picked.
+    #[arg(long, value_enum, default_value = "uniform")]
+    distribution: Distribution,
+
+    /// Exponent of the `--distribution zipf`: the tenant of rank `r` gets queries in proportion
+    /// to `1 / r^s`.
+    #[arg(long, default_value_t = 1.0)]
+    zipf_exponent: f64,
+
+    /// Seed of the tenants and queries picked.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Seconds the queries run for.
+    #[arg(long, default_value_t = 60)]
+    duration_secs: u64,
+
+    /// Number of requests in flight, while loading the tenants and while querying them.
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: u16,
+
+    /// Number of documents per upsert request.
+    #[arg(long, default_value_t = 1_000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// Don't read stdin: query the tenants left by an earlier run with `--keep`.
+    #[arg(long)]
+    skip_load: bool,
+
+    /// Leave the tenant namespaces in place.
+    #[arg(long)]
+    keep: bool,
+
+    /// Print the number of queries, cold-start latency and mean latency of every tenant.
+    #[arg(long)]
+    per_tenant: bool,
+
+    /// Maximum number of attempts per request.
+    #[arg(long, default_value_t = 8)]
+    max_attempts: u32,
+
+    /// Milliseconds between two checks of the index status of a tenant before the queries.
+    #[arg(long, default_value_t = 500)]
+    poll_interval_ms: u64,
+
+    /// Consistency level of the queries.
+    #[arg(long, value_enum, default_value = "eventual")]
+    consistency: Consistency,
+
+    /// Replace the `filter` tags of all documents with synthetic ones, like `build_index
+    /// --synthetic-filters`.
+    #[arg(long)]
+    synthetic_filters: bool,
+
+    /// Selectivities, in percent, of the `filter` tags matched by the `*_FILTER_<pct>%` commands,
+    /// and of the `--synthetic-filters` tags.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = corpus::FILTER_SELECTIVITIES,
+        value_parser = corpus::parse_selectivity
+    )]
+    filter_selectivities: Vec<f64>,
+
+    /// Add the synthetic attributes to all documents, like `build_index --synthetic-attributes`.
+    #[arg(long)]
+    synthetic_attributes: bool,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Distribution {
+    /// Every tenant gets as many queries.
+    Uniform,
+    /// A few tenants get most queries, and the long tail is rarely queried.
+    Zipf,
+}
+
+/// Picks tenants following the `--distribution`.
+struct TenantPicker {
+    /// Cumulative weights of the tenants, empty for the uniform distribution.
+    cumulative: Vec<f64>,
+    tenants: u64,
+}
+
+impl TenantPicker {
+    fn new(args: &Args) -> TenantPicker {
+        let cumulative = match args.distribution {
+            Distribution::Uniform => vec![],
+            Distribution::Zipf => (1..=args.tenants)
+                .scan(0.0, |total, rank| {
+                    *total += 1.0 / (rank as f64).powf(args.zipf_exponent);
+                    Some(*total)
+                })
+                .collect(),
+        };
+        TenantPicker {
+            cumulative,
+            tenants: args.tenants,
+        }
+    }
+
+    fn pick(&self, rng: &mut SplitMix64) -> usize {
+        match self.cumulative.last() {
+            None => rng.below(self.tenants) as usize,
+            Some(total) => {
+                let target = rng.next_f64() * total;
+                self.cumulative.partition_point(|&weight| weight <= target)
+            }
+        }
+    }
+}
+
+/// What the workers share.
+struct Workload {
+    tenants: Vec<Client>,
+    queries: Vec<Query>,
+    picker: TenantPicker,
+    /// Whether every tenant got its first query.
+    queried: Vec<AtomicBool>,
+}
+
+/// What a worker measured of a tenant.
+#[derive(Clone, Default)]
+struct TenantStats {
+    queries: u64,
+    total: Duration,
+    cold_start: Option<Duration>,
+}
+
+/// What a worker measured.
+struct WorkerStats {
+    tenants: Vec<TenantStats>,
+    warm: LatencyHistogram,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let api_keys = ApiKeys::from_env(Access::ReadWrite)?;
+    if !(args.zipf_exponent > 0.0 && args.zipf_exponent.is_finite()) {
+        anyhow::bail!("--zipf-exponent must be positive");
+    }
+    let client = Client::new(&args.client, &api_keys, args.max_attempts)?;
+    let tenants = (0..args.tenants)
+        .map(|i| client.with_namespace(&format!("{}-t{i}", client.namespace())))
+        .collect::<Vec<_>>();
+    let options = ParseOptions {
+        consistency: args.consistency,
+        filter_selectivities: args.filter_selectivities.clone(),
+        synthetic_attributes: args.synthetic_attributes,
+        ..ParseOptions::default()
+    };
+    let (queries, skipped) = query::load_single_request_queries(&args.queries, &options)?;
+    println!("loaded {} queries, skipped {skipped}", queries.len());
+    if !args.skip_load {
+        load_tenants(&args, &tenants).await?;
+    }
+    let index_start = Instant::now();
+    for tenant in &tenants {
+        wait_until_indexed(tenant, args.poll_interval_ms).await?;
+    }
+    println!("tenants indexed {:?} after loading", index_start.elapsed());
+
+    let workload = Arc::new(Workload {
+        queried: tenants.iter().map(|_| AtomicBool::new(false)).collect(),
+        tenants,
+        queries,
+        picker: TenantPicker::new(&args),
+    });
+    let duration = Duration::from_secs(args.duration_secs);
+    let deadline = Instant::now() + duration;
+    let mut workers = JoinSet::new();
+    for worker in 0..u64::from(args.concurrency) {
+        let workload = workload.clone();
+        let rng = SplitMix64::new(args.seed ^ worker.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        workers.spawn(async move { work(&workload, rng, deadline).await });
+    }
+    let mut tenants = vec![TenantStats::default(); workload.tenants.len()];
+    let mut cold = LatencyHistogram::default();
+    let mut warm = LatencyHistogram::default();
+    while let Some(joined) = workers.join_next().await {
+        let stats = joined??;
+        warm.merge(&stats.warm);
+        for (tenant, worker) in tenants.iter_mut().zip(stats.tenants) {
+            tenant.queries += worker.queries;
+            tenant.total += worker.total;
+            if let Some(latency) = worker.cold_start {
+                tenant.cold_start = Some(latency);
+                cold.record(latency);
+            }
+        }
+    }
+
+    let queried = tenants.iter().filter(|tenant| tenant.queries > 0).count();
+    println!(
+        "throughput: {:.1} queries/s over {queried} of {} tenants",
+        (cold.len() + warm.len()) as f64 / duration.as_secs_f64(),
+        tenants.len()
+    );
+    println!("first query of a tenant: {}", cold.summary());
+    println!("other queries: {}", warm.summary());
+    if args.per_tenant {
+        println!("tenant\tqueries\tcold_start_ms\tmean_ms");
+        for (tenant, stats) in workload.tenants.iter().zip(&tenants) {
+            if stats.queries == 0 {
+                continue;
+            }
+            println!(
+                "{}\t{}\t{:.3}\t{:.3}",
+                tenant.namespace(),
+                stats.queries,
+                stats.cold_start.unwrap_or_default().as_secs_f64() * 1000.0,
+                stats.total.as_secs_f64() * 1000.0 / stats.queries as f64
+            );
+        }
+    }
+
+    if !args.keep {
+        for tenant in &workload.tenants {
+            tenant.delete_namespace().await?;
+        }
+    }
+    otlp::flush().await;
+    Ok(())
+}
+
+/// Reads the corpus from stdin and upserts every document to its tenant, in batches of
+/// `--batch-size` documents.
+async fn load_tenants(args: &Args, tenants: &[Client]) -> Result<(), anyhow::Error> {
+    let mut schema = serde_json::json!({
+        "text": {
+            "type": "string",
+            "full_text_search": true,
+        },
+    });
+    if args.synthetic_attributes {
+        corpus::add_synthetic_schema(&mut schema);
+    }
+    let start = Instant::now();
+    let mut pending = vec![vec![]; tenants.len()];
+    let mut in_flight = JoinSet::new();
+    let mut upserted = 0;
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut doc: Document = serde_json::from_str(&line)?;
+        if doc.id.is_empty() {
+            anyhow::bail!("every document needs an id to be assigned a tenant");
+        }
+        corpus::augment(
+            &mut doc,
+            args.synthetic_filters
+                .then_some(args.filter_selectivities.as_slice()),
+            args.synthetic_attributes,
+        );
+        let tenant = corpus::shard(&doc.id, tenants.len());
+        pending[tenant].push(doc);
+        if pending[tenant].len() < args.batch_size as usize {
+            continue;
+        }
+        while in_flight.len() >= usize::from(args.concurrency) {
+            upserted += in_flight.join_next().await.unwrap()??;
+        }
+        let batch = std::mem::take(&mut pending[tenant]);
+        let (client, schema) = (tenants[tenant].clone(), schema.clone());
+        in_flight.spawn(async move { upsert(&client, &batch, &schema).await });
+    }
+    for (tenant, batch) in pending.into_iter().enumerate() {
+        if batch.is_empty() {
+            continue;
+        }
+        while in_flight.len() >= usize::from(args.concurrency) {
+            upserted += in_flight.join_next().await.unwrap()??;
+        }
+        let (client, schema) = (tenants[tenant].clone(), schema.clone());
+        in_flight.spawn(async move { upsert(&client, &batch, &schema).await });
+    }
+    while let Some(joined) = in_flight.join_next().await {
+        upserted += joined??;
+    }
+    println!(
+        "loaded {upserted} documents into {} tenants in {:?}",
+        tenants.len(),
+        start.elapsed()
+    );
+    Ok(())
+}
+
+async fn upsert(
+    client: &Client,
+    batch: &[Document],
+    schema: &serde_json::Value,
+) -> Result<u64, anyhow::Error> {
+    let upserted = client.upsert_rows(batch, Some(schema), None).await?;
+    Ok(upserted.body.rows_affected)
+}
+
+/// Sends one random query at a time to a tenant picked by the `--distribution` until
+/// `deadline`.
+async fn work(
+    workload: &Workload,
+    mut rng: SplitMix64,
+    deadline: Instant,
+) -> Result<WorkerStats, anyhow::Error> {
+    let mut stats = WorkerStats {
+        tenants: vec![TenantStats::default(); workload.tenants.len()],
+        warm: LatencyHistogram::default(),
+    };
+    while Instant::now() < deadline {
+        let tenant = workload.picker.pick(&mut rng);
+        let query = &workload.queries[rng.below(workload.queries.len() as u64) as usize];
+        let client = &workload.tenants[tenant];
+        // Only the worker that flips the flag times the first query of the tenant.
+        let first = !workload.queried[tenant].swap(true, Ordering::Relaxed);
+        let start = Instant::now();
+        if query.count_only {
+            client.query::<AggregationResponse>(&query.body).await?;
+        } else {
+            client.query::<QueryResponse>(&query.body).await?;
+        }
+        let latency = start.elapsed();
+        let tenant_stats = &mut stats.tenants[tenant];
+        tenant_stats.queries += 1;
+        tenant_stats.total += latency;
+        if first {
+            tenant_stats.cold_start = Some(latency);
+        } else {
+            stats.warm.record(latency);
+        }
+    }
+    Ok(stats)
+}
+
+/// Polls the index status until it is up to date.
+async fn wait_until_indexed(client: &Client, poll_interval_ms: u64) -> Result<(), anyhow::Error> {
+    while client.metadata().await?.index.status != "up-to-date" {
+        tokio::time::sleep(Duration::from_millis(poll_interval_ms)).await;
+    }
+    Ok(())
+}
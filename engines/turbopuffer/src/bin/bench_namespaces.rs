@@ -0,0 +1,112 @@
+use std::time::{Instant, SystemTime};
+
+use clap::Parser;
+use turbopuffer_bench::client::{Access, ApiKeys, Client, ClientArgs, QueryResponse};
+use turbopuffer_bench::otlp;
+use turbopuffer_bench::stats::LatencyHistogram;
+
+/// Creates, queries and deletes short-lived namespaces `<namespace>-lifecycle-<run>-<i>` one
+/// after the other, like a service creating a namespace per user session, and reports the
+/// latency of every step of their lifecycle: the first write, which creates the namespace, the
+/// first query, a second write, and the deletion.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    client: ClientArgs,
+
+    /// Number of namespaces created and deleted.
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    cycles: u64,
+
+    /// Number of documents of every write.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    docs: u64,
+
+    /// Maximum number of attempts per request.
+    #[arg(long, default_value_t = 8)]
+    max_attempts: u32,
+}
+
+/// The latencies of the steps of the lifecycle.
+#[derive(Default)]
+struct Lifecycle {
+    create: LatencyHistogram,
+    first_query: LatencyHistogram,
+    write: LatencyHistogram,
+    delete: LatencyHistogram,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let api_keys = ApiKeys::from_env(Access::ReadWrite)?;
+    let client = Client::new(&args.client, &api_keys, args.max_attempts)?;
+    let schema = serde_json::json!({
+        "text": {
+            "type": "string",
+            "full_text_search": true,
+        },
+    });
+    // Distinct across runs, so that namespaces left by an interrupted run don't get in the way.
+    let run = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_nanos() as u64;
+
+    let mut lifecycle = Lifecycle::default();
+    let start = Instant::now();
+    for cycle in 0..args.cycles {
+        let namespace = format!("{}-lifecycle-{run:x}-{cycle}", client.namespace());
+        let client = client.with_namespace(&namespace);
+        let rows = |revision: u64| {
+            (0..args.docs)
+                .map(|i| {
+                    serde_json::json!({
+                        "id": format!("doc-{i}"),
+                        "text": format!("session {cycle} document {i} revision {revision}"),
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let request_start = Instant::now();
+        client.upsert_rows(&rows(0), Some(&schema), None).await?;
+        lifecycle.create.record(request_start.elapsed());
+
+        let body = serde_json::json!({
+            "rank_by": ["text", "BM25", "session"],
+            "top_k": args.docs,
+            "consistency": {"level": "strong"},
+        });
+        let request_start = Instant::now();
+        let response = client.query::<QueryResponse>(&body).await?;
+        lifecycle.first_query.record(request_start.elapsed());
+        if response.body.rows.len() as u64 != args.docs {
+            anyhow::bail!(
+                "{namespace}: the first query returned {} of {} documents",
+                response.body.rows.len(),
+                args.docs
+            );
+        }
+
+        let request_start = Instant::now();
+        client.upsert_rows(&rows(1), None, None).await?;
+        lifecycle.write.record(request_start.elapsed());
+
+        let request_start = Instant::now();
+        client.delete_namespace().await?;
+        lifecycle.delete.record(request_start.elapsed());
+    }
+
+    println!("create (first write): {}", lifecycle.create.summary());
+    println!("first query: {}", lifecycle.first_query.summary());
+    println!("second write: {}", lifecycle.write.summary());
+    println!("delete: {}", lifecycle.delete.summary());
+    println!(
+        "{} namespaces in {:?} ({:.2} namespaces/s)",
+        args.cycles,
+        start.elapsed(),
+        args.cycles as f64 / start.elapsed().as_secs_f64()
+    );
+    otlp::flush().await;
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::fmt::Write;
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use turbopuffer_bench::client::{Access, ApiKeys, Client, ClientArgs};
+use turbopuffer_bench::corpus::{self, Document};
+use turbopuffer_bench::otlp;
+use turbopuffer_bench::stats::LatencyHistogram;
+
+/// Re-upserts a sample of the corpus read from stdin with slightly changed text, round after
+/// round, into a namespace built by `build_index` from the same corpus. Reports the upsert
+/// latency and how long the index takes to be up to date again after every round.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    client: ClientArgs,
+
+    /// Fraction of the documents re-upserted in every round, chosen by hash of their id.
+    #[arg(long, default_value_t = 0.01, value_parser = corpus::parse_fraction)]
+    fraction: f64,
+
+    /// Number of rounds.
+    #[arg(long, default_value_t = 5)]
+    rounds: u32,
+
+    /// Maximum number of documents per upsert request.
+    #[arg(long, default_value_t = 1_000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// Maximum number of attempts per upsert request.
+    #[arg(long, default_value_t = 8)]
+    max_attempts: u32,
+
+    /// The namespace was built with `--synthetic-filters`.
+    #[arg(long)]
+    synthetic_filters: bool,
+
+    /// The namespace was built with `--synthetic-attributes`.
+    #[arg(long)]
+    synthetic_attributes: bool,
+
+    /// The `--filter-selectivities` the namespace was built with.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = corpus::FILTER_SELECTIVITIES,
+        value_parser = corpus::parse_selectivity
+    )]
+    filter_selectivities: Vec<f64>,
+
+    /// The `--vector-field` the namespace was built with, if any.
+    #[arg(long)]
+    vector_field: Option<String>,
+
+    /// The `--distance-metric` the namespace was built with, if it has vectors.
+    #[arg(long, default_value = "cosine_distance")]
+    distance_metric: String,
+
+    /// Milliseconds between two checks of the index status after a round.
+    #[arg(long, default_value_t = 500)]
+    poll_interval_ms: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let api_keys = ApiKeys::from_env(Access::ReadWrite)?;
+    let client = Client::new(&args.client, &api_keys, args.max_attempts)?;
+
+    let mut sample = vec![];
+    let mut read = 0;
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        read += 1;
+        let mut doc: Document = serde_json::from_str(&line)?;
+        if !corpus::sampled(&doc.id, args.fraction) {
+            continue;
+        }
+        if let Some(field) = &args.vector_field {
+            let Some(vector) = doc.extra.remove(field) else {
+                anyhow::bail!("document {:?} has no {field:?} field", doc.id);
+            };
+            doc.vector = Some(serde_json::from_value(vector)?);
+        }
+        sample.push(doc);
+    }
+    if sample.is_empty() {
+        anyhow::bail!("no document sampled out of {read}, increase --fraction");
+    }
+    println!("sampled {} of {read} documents", sample.len());
+    let text_lengths = sample.iter().map(|doc| doc.text.len()).collect::<Vec<_>>();
+    let distance_metric = args
+        .vector_field
+        .is_some()
+        .then_some(args.distance_metric.as_str());
+
+    let mut latencies = LatencyHistogram::default();
+    let mut catch_up = LatencyHistogram::default();
+    for round in 1..=args.rounds {
+        for (doc, &length) in sample.iter_mut().zip(&text_lengths) {
+            doc.text.truncate(length);
+            write!(doc.text, " revision{round}")?;
+            corpus::augment(
+                doc,
+                args.synthetic_filters
+                    .then_some(args.filter_selectivities.as_slice()),
+                args.synthetic_attributes,
+            );
+        }
+        let mut round_latencies = LatencyHistogram::default();
+        let start = Instant::now();
+        for batch in sample.chunks(args.batch_size as usize) {
+            let request_start = Instant::now();
+            let upserted = client.upsert_rows(batch, None, distance_metric).await?;
+            round_latencies.record(request_start.elapsed());
+            latencies.record(request_start.elapsed());
+            if upserted.body.rows_affected != batch.len() as u64 {
+                anyhow::bail!(
+                    "upserted {} documents, but the server acknowledged {}",
+                    batch.len(),
+                    upserted.body.rows_affected
+                );
+            }
+        }
+        let written = start.elapsed();
+        let index_lag = wait_until_indexed(&client, args.poll_interval_ms).await?;
+        catch_up.record(index_lag);
+        println!(
+            "round {round}: upserted {} documents in {written:?} ({:.0} docs/s), upsert latency {}; index up-to-date {index_lag:?} later",
+            sample.len(),
+            sample.len() as f64 / written.as_secs_f64(),
+            round_latencies.summary(),
+        );
+    }
+    println!("upsert latency: {}", latencies.summary());
+    println!("index catch-up: {}", catch_up.summary());
+    otlp::flush().await;
+    Ok(())
+}
+
+/// Polls the index status until it is up to date, returning how long it took.
+async fn wait_until_indexed(
+    client: &Client,
+    poll_interval_ms: u64,
+) -> Result<Duration, anyhow::Error> {
+    let start = Instant::now();
+    loop {
+        if client.metadata().await?.index.status == "up-to-date" {
+            return Ok(start.elapsed());
+        }
+        tokio::time::sleep(Duration::from_millis(poll_interval_ms)).await;
+    }
+}
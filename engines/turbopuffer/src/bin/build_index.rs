@@ -1,138 +1,1544 @@
-use std::io::BufRead;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufRead, Write};
 use std::mem;
-use std::sync::LazyLock;
-use std::time::Duration;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use serde::Deserialize;
-use tokio::task::JoinSet;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
+use turbopuffer_bench::client::{
+    Access, AggregationResponse, ApiKeys, Client, ClientArgs, Compression, Upserted,
+};
+use turbopuffer_bench::corpus::{self, Document};
+use turbopuffer_bench::manifest;
+use turbopuffer_bench::metrics::METRICS;
+use turbopuffer_bench::otlp;
+use turbopuffer_bench::progress::Progress;
+use turbopuffer_bench::random::SplitMix64;
+use turbopuffer_bench::rate_limit::RateLimiter;
 
-const API_URL: &str = "http://localhost:3001";
-const API_KEY: LazyLock<String> = LazyLock::new(|| {
-    std::env::var("TURBOPUFFER_API_KEY").expect("TURBOPUFFER_API_KEY must be set")
-});
+/// Reads JSON documents from stdin or `--input` and upserts them into a fresh turbopuffer
+/// namespace.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    client: ClientArgs,
 
-const NAMESPACE: &str = "search-benchmark-game";
-const BATCH_SIZE: usize = 10_000;
-const MAX_CONCURRENCY: usize = 32;
+    /// Read the corpus from this file instead of stdin.
+    #[arg(long)]
+    input: Option<PathBuf>,
+
+    /// Format of the corpus.
+    #[arg(long, value_enum, default_value = "jsonl")]
+    format: InputFormat,
+
+    /// Parquet column holding the document ids. Integer ids are converted to strings.
+    #[arg(long, default_value = "id")]
+    id_column: String,
+
+    /// Parquet column holding the document text.
+    #[arg(long, default_value = "text")]
+    text_column: String,
+
+    /// Parquet column holding the `filter` tags, a list of strings. Documents get synthetic tags
+    /// without it.
+    #[arg(long)]
+    filter_column: Option<String>,
+
+    /// Index, from 0, of the TSV column holding the document ids.
+    #[arg(long, default_value_t = 0)]
+    id_col: usize,
+
+    /// Index, from 0, of the TSV column holding the document text.
+    #[arg(long, default_value_t = 1)]
+    text_col: usize,
+
+    /// Maximum number of chunks of input lines parsed in parallel, on tokio's blocking pool.
+    /// Defaults to the number of CPUs.
+    #[arg(long, default_value_t = std::thread::available_parallelism().map_or(4, usize::from))]
+    parse_threads: usize,
+
+    /// Maximum number of documents per upsert request.
+    #[arg(long, default_value_t = 10_000, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// Maximum size of the serialized documents of an upsert request. A batch is sent as soon as
+    /// it reaches this size or `--batch-size` documents, whichever comes first.
+    #[arg(long, default_value_t = 32 << 20)]
+    max_batch_bytes: usize,
+
+    /// Spread the documents over this many namespaces, `<namespace>-0` to `<namespace>-<N-1>`, by
+    /// hash of their id. Every batch is split into one upsert request per shard.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    shards: u16,
+
+    /// Maximum number of upsert requests in flight.
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u64).range(1..))]
+    concurrency: u64,
+
+    /// Adjust the number of upsert requests in flight, up to `--concurrency`, to what the server
+    /// sustains: add one per round of requests completing quickly, and halve it when a request is
+    /// retried, e.g. on 429, or is more than twice as slow per MB as the fastest so far. The
+    /// concurrency settled on is reported at the end.
+    #[arg(long)]
+    adaptive_concurrency: bool,
+
+    /// Limit the serialized size of the documents of the batch being filled and of the batches
+    /// in flight, held in memory until they are acknowledged, to this many MB, whatever the
+    /// concurrency. A single batch larger than that is still sent on its own.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_inflight_mb: Option<u64>,
+
+    /// Maximum number of attempts per upsert request. Requests failing with 429, 5xx or a
+    /// connection error are retried with exponential backoff.
+    #[arg(long, default_value_t = 8)]
+    max_attempts: u32,
+
+    /// Read the schema of the namespace from this JSON file instead of deriving it from the
+    /// options below. It must declare the `text` attribute with full-text search, and the
+    /// synthetic attributes must stay filterable for the commands using them.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "bm25_k1",
+            "bm25_b",
+            "remove_stopwords",
+            "language",
+            "stemming",
+            "case_sensitive",
+            "tokenizer",
+        ]
+    )]
+    schema: Option<PathBuf>,
+
+    /// BM25 term frequency saturation parameter of the `text` attribute.
+    #[arg(long, default_value_t = 0.9, value_parser = parse_k1)]
+    bm25_k1: f64,
+
+    /// BM25 document length normalization parameter of the `text` attribute, in [0, 1].
+    #[arg(long, default_value_t = 0.4, value_parser = parse_b)]
+    bm25_b: f64,
+
+    /// Remove stopwords of `--language` from the `text` attribute when indexing and querying.
+    #[arg(long)]
+    remove_stopwords: bool,
+
+    /// Language of the `text` attribute, used for stopwords and stemming.
+    #[arg(
+        long,
+        default_value = "english",
+        value_parser = clap::builder::PossibleValuesParser::new(LANGUAGES)
+    )]
+    language: String,
+
+    /// Stem the terms of the `text` attribute, e.g. with the Porter stemmer for English.
+    #[arg(long)]
+    stemming: bool,
+
+    /// Match terms of the `text` attribute case-sensitively instead of case-folding them.
+    #[arg(long)]
+    case_sensitive: bool,
+
+    /// Tokenizer splitting the `text` attribute into terms.
+    #[arg(
+        long,
+        default_value = "word_v1",
+        value_parser = clap::builder::PossibleValuesParser::new(["word_v0", "word_v1", "word_v2"])
+    )]
+    tokenizer: String,
+
+    /// Index this field of the JSON documents for full-text search as the `title` attribute,
+    /// with the same settings as `text`, for the `TITLE_BODY_*` commands.
+    #[arg(long)]
+    title_field: Option<String>,
+
+    /// Comma-separated fields of the JSON documents to upsert besides `id`, `text`, `filter` and
+    /// the fields of `--title-field` and `--vector-field`. Other fields are dropped. All fields
+    /// are upserted by default.
+    #[arg(long, value_delimiter = ',')]
+    keep_fields: Option<Vec<String>>,
+
+    /// Truncate the text of the documents to this many bytes, at a character boundary, before
+    /// indexing it.
+    #[arg(long)]
+    max_text_bytes: Option<usize>,
+
+    /// Give the documents without an id, or with an empty one, an id derived from their line
+    /// number, so that a resumed ingest assigns the same ids. By default they are an error.
+    #[arg(long, value_enum, default_value = "none")]
+    generate_ids: IdGeneration,
+
+    /// Ingest a precomputed embedding per document into the `vector` attribute, taken from this
+    /// field of the JSON documents.
+    #[arg(long, conflicts_with = "vectors")]
+    vector_field: Option<String>,
+
+    /// Ingest a precomputed embedding per document into the `vector` attribute, read from this
+    /// file with one JSON array per line, in the same order as the documents.
+    #[arg(long)]
+    vectors: Option<PathBuf>,
+
+    /// Distance metric of the `vector` attribute, used by the ANN_* commands.
+    #[arg(
+        long,
+        default_value = "cosine_distance",
+        value_parser = clap::builder::PossibleValuesParser::new(["cosine_distance", "euclidean_squared"])
+    )]
+    distance_metric: String,
+
+    /// Compress upsert request bodies.
+    #[arg(long, value_enum, default_value = "none")]
+    compression: Compression,
+
+    /// Send the documents of every upsert request as one array per attribute (`upsert_columns`)
+    /// instead of one object per document (`upsert_rows`).
+    #[arg(long)]
+    columnar: bool,
+
+    /// Limit the number of upsert requests sent per second.
+    #[arg(long, value_parser = parse_rate)]
+    max_writes_per_sec: Option<f64>,
+
+    /// Limit the size of the upsert requests sent per second, in MB of uncompressed JSON.
+    #[arg(long, value_parser = parse_rate)]
+    max_mb_per_sec: Option<f64>,
+
+    /// Record the number of input lines whose documents are acknowledged in this file, so that
+    /// an interrupted ingest can be continued with `--resume`.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Continue the ingest recorded in `--checkpoint`: keep the namespace and skip the input lines
+    /// already acknowledged. Upserts are idempotent, so lines sent but not yet recorded are just
+    /// written again.
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Serve Prometheus metrics on this address, e.g. `127.0.0.1:9090`.
+    #[cfg(feature = "metrics")]
+    #[arg(long)]
+    metrics_addr: Option<std::net::SocketAddr>,
+
+    /// Append one JSON record per upsert request to this file, with its latency, size, HTTP
+    /// status and number of attempts, or its error, to analyze the ingest afterwards.
+    #[arg(long)]
+    ingest_log: Option<PathBuf>,
+
+    /// Record the code, command line, schema, batching and concurrency settings, corpus size and
+    /// timings of the build in the `build_index` section of this JSON file, along with the
+    /// `do_query` section of the runs against the index.
+    #[arg(long, default_value = "run-manifest.json")]
+    run_manifest: PathBuf,
+
+    /// Seconds between progress reports.
+    #[arg(long, default_value_t = 10)]
+    progress_interval: u64,
+
+    /// Seconds between two checks of the index status once all documents are written.
+    #[arg(long, default_value_t = 10.0, value_parser = parse_seconds)]
+    poll_interval: f64,
+
+    /// Fail if the index isn't up to date this many seconds after all documents are written.
+    /// Waits indefinitely by default.
+    #[arg(long, value_parser = parse_seconds)]
+    index_timeout: Option<f64>,
+
+    /// Count the documents containing any of these terms every `--probe-interval` seconds during
+    /// the ingest and until the index is up to date, with eventual consistency, and report when
+    /// the first ones became queryable and when the count last changed.
+    #[arg(long)]
+    probe_query: Option<String>,
+
+    /// Seconds between two `--probe-query` counts.
+    #[arg(long, default_value_t = 5.0, value_parser = parse_seconds)]
+    probe_interval: f64,
+
+    /// Replace the `filter` tags of all documents with synthetic ones, for corpora without tags
+    /// of their own. Every document gets the tag `<pct>%` of each `--filter-selectivities` with
+    /// probability `<pct>` percent.
+    #[arg(long)]
+    synthetic_filters: bool,
+
+    /// Selectivities, in percent, of the `--synthetic-filters` tags.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = corpus::FILTER_SELECTIVITIES,
+        value_parser = corpus::parse_selectivity,
+        requires = "synthetic_filters"
+    )]
+    filter_selectivities: Vec<f64>,
+
+    /// Add the synthetic `timestamp`, `doc_length`, `group` and `code` attributes to all
+    /// documents, for the `*_RANGE_*`, `*_GLOB_*`, `*_REGEX_*`, `*_SORT_DOC_LENGTH_*` and
+    /// `*_GROUP_BY` commands.
+    #[arg(long)]
+    synthetic_attributes: bool,
+}
+
+/// Format of the corpus.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum InputFormat {
+    /// One JSON document per line.
+    Jsonl,
+    /// One document per line with tab-separated columns, like the TREC collections, the columns
+    /// of the id and the text given by `--id-col` and `--text-col`.
+    Tsv,
+    /// A Parquet file, given with `--input`, whose columns are mapped to attributes with
+    /// `--id-column`, `--text-column` and `--filter-column`. Requires building with
+    /// `--features parquet`.
+    Parquet,
+}
+
+/// How to identify documents without an id.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum IdGeneration {
+    /// Fail the ingest.
+    None,
+    /// The line number, which may clash with the ids of other documents.
+    Sequential,
+    /// A version 4 UUID.
+    Uuid,
+}
+
+/// The corpus as JSON lines.
+struct Input {
+    lines: Box<dyn BufRead + Send>,
+    /// Size of the input if known, used to estimate the remaining time.
+    len: Option<u64>,
+}
+
+/// Totals over all upsert requests.
+#[derive(Default)]
+struct Written {
+    rows_affected: u64,
+    body_bytes: usize,
+    sent_bytes: usize,
+    /// Number of requests that needed more than one attempt, and the sum of the latencies of
+    /// the requests, retries included.
+    retried_requests: u64,
+    latency: Duration,
+}
+
+impl Written {
+    fn add(&mut self, upserted: Upserted) {
+        self.rows_affected += upserted.rows_affected;
+        self.body_bytes += upserted.body_bytes;
+        self.sent_bytes += upserted.sent_bytes;
+    }
+
+    fn merge(&mut self, other: Written) {
+        self.rows_affected += other.rows_affected;
+        self.body_bytes += other.body_bytes;
+        self.sent_bytes += other.sent_bytes;
+        self.retried_requests += other.retried_requests;
+        self.latency += other.latency;
+    }
+}
+
+/// The serialized size of the documents of the batches in flight, against `--max-inflight-mb`.
+struct InFlightBytes {
+    budget: Option<usize>,
+    total: usize,
+    peak: usize,
+    /// Size of every batch in flight, by first input line.
+    batches: HashMap<u64, usize>,
+}
+
+impl InFlightBytes {
+    fn new(args: &Args) -> InFlightBytes {
+        InFlightBytes {
+            budget: args.max_inflight_mb.map(|mb| mb as usize * 1_000_000),
+            total: 0,
+            peak: 0,
+            batches: HashMap::new(),
+        }
+    }
+
+    /// Whether the batches in flight and a batch of `pending` bytes exceed the budget.
+    fn over_budget(&self, pending: usize) -> bool {
+        self.budget
+            .is_some_and(|budget| self.total + pending > budget)
+    }
+
+    fn sent(&mut self, first_line: u64, bytes: usize) {
+        self.batches.insert(first_line, bytes);
+        self.total += bytes;
+        self.peak = self.peak.max(self.total);
+    }
+
+    fn completed(&mut self, lines: &Range<u64>) {
+        if let Some(bytes) = self.batches.remove(&lines.start) {
+            self.total -= bytes;
+        }
+    }
+}
+
+/// Initial number of upsert requests in flight with `--adaptive-concurrency`.
+const INITIAL_CONCURRENCY: f64 = 4.0;
+
+/// How much slower per MB than the fastest request so far a request may be before
+/// `--adaptive-concurrency` takes it as a sign of overload.
+const LATENCY_TOLERANCE: f64 = 2.0;
+
+/// The limit on the number of upsert requests in flight: `--concurrency`, or with
+/// `--adaptive-concurrency`, a limit adjusted with additive increase and multiplicative decrease,
+/// like TCP congestion control.
+struct Concurrency {
+    adaptive: bool,
+    max: f64,
+    limit: f64,
+    /// Lowest latency per MB of request body so far, that of a server keeping up.
+    best_ms_per_mb: f64,
+    /// First line of the last batch sent, and that value when the limit was last decreased:
+    /// the batches sent before a decrease don't decrease it again.
+    last_sent: u64,
+    decreased_at: Option<u64>,
+    decreases: u64,
+    /// Sum of the limits when batches completed, and the number of completed batches.
+    limit_sum: f64,
+    completed_batches: u64,
+}
+
+impl Concurrency {
+    fn new(args: &Args) -> Concurrency {
+        let max = args.concurrency as f64;
+        Concurrency {
+            adaptive: args.adaptive_concurrency,
+            max,
+            limit: if args.adaptive_concurrency {
+                INITIAL_CONCURRENCY.min(max)
+            } else {
+                max
+            },
+            best_ms_per_mb: f64::INFINITY,
+            last_sent: 0,
+            decreased_at: None,
+            decreases: 0,
+            limit_sum: 0.0,
+            completed_batches: 0,
+        }
+    }
+
+    /// Maximum number of upsert requests in flight.
+    fn limit(&self) -> usize {
+        self.limit as usize
+    }
+
+    /// Records that the batch of the input lines starting at `first_line` was sent.
+    fn sent(&mut self, first_line: u64) {
+        self.last_sent = first_line;
+    }
+
+    /// Adjusts the limit to the requests of the batch of `lines`.
+    fn completed(&mut self, lines: &Range<u64>, written: &Written) {
+        if !self.adaptive {
+            return;
+        }
+        self.limit_sum += self.limit;
+        self.completed_batches += 1;
+        let ms_per_mb =
+            written.latency.as_secs_f64() * 1e3 / (written.body_bytes.max(1) as f64 / 1e6);
+        self.best_ms_per_mb = self.best_ms_per_mb.min(ms_per_mb);
+        let overloaded =
+            written.retried_requests > 0 || ms_per_mb > LATENCY_TOLERANCE * self.best_ms_per_mb;
+        if !overloaded {
+            // One more request in flight per round of `limit` requests.
+            self.limit = (self.limit + 1.0 / self.limit).min(self.max);
+        } else if self.decreased_at.is_none_or(|at| lines.start > at) {
+            self.limit = (self.limit / 2.0).max(1.0);
+            self.decreased_at = Some(self.last_sent);
+            self.decreases += 1;
+        }
+    }
+}
+
+/// Client-side limits on the rate of upsert requests.
+struct Limits {
+    writes: Option<RateLimiter>,
+    bytes: Option<RateLimiter>,
+}
+
+impl Limits {
+    /// Waits until a request of `bytes` bytes may be sent.
+    async fn wait(&self, bytes: usize) {
+        if let Some(writes) = &self.writes {
+            writes.acquire(1.0).await;
+        }
+        if let Some(limiter) = &self.bytes {
+            limiter.acquire(bytes as f64).await;
+        }
+    }
+}
+
+/// Tracks the input lines whose documents are acknowledged, and persists the longest fully
+/// acknowledged prefix of the input. Batches complete out of order.
+struct Checkpoint {
+    path: Option<PathBuf>,
+    state: CheckpointState,
+    /// Acknowledged line ranges after `state.lines`, by start.
+    pending: BTreeMap<u64, u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckpointState {
+    namespace: String,
+    /// Number of input lines, blank ones included, that needn't be sent again.
+    lines: u64,
+}
+
+impl Checkpoint {
+    fn new(path: Option<PathBuf>, namespace: &str) -> Checkpoint {
+        Checkpoint {
+            path,
+            state: CheckpointState {
+                namespace: namespace.to_string(),
+                lines: 0,
+            },
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the checkpoint file, returning the number of lines to skip.
+    fn load(&mut self) -> Result<u64, anyhow::Error> {
+        let path = self
+            .path
+            .as_deref()
+            .expect("--resume requires --checkpoint");
+        let state: CheckpointState = serde_json::from_slice(&std::fs::read(path)?)?;
+        if state.namespace != self.state.namespace {
+            anyhow::bail!(
+                "checkpoint {} is for namespace {:?}, not {:?}",
+                path.display(),
+                state.namespace,
+                self.state.namespace
+            );
+        }
+        self.state.lines = state.lines;
+        Ok(state.lines)
+    }
+
+    fn acknowledge(&mut self, lines: Range<u64>) -> Result<(), anyhow::Error> {
+        self.pending.insert(lines.start, lines.end);
+        let before = self.state.lines;
+        while let Some(end) = self.pending.remove(&self.state.lines) {
+            self.state.lines = end;
+        }
+        match &self.path {
+            Some(path) if self.state.lines != before => write_atomically(path, &self.state),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Sets the returned flag on Ctrl-C, for the ingest to stop reading the input and wait for the
+/// batches in flight, so that the namespace holds a known prefix of the input. A second Ctrl-C
+/// exits at once.
+fn watch_interrupts() -> Arc<AtomicBool> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("interrupted: waiting for the batches in flight, Ctrl-C again to exit at once");
+        flag.store(true, Ordering::Relaxed);
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+    interrupted
+}
+
+/// Writes `value` as JSON to a temporary file renamed to `path`, so that a crash never leaves a
+/// truncated file behind.
+fn write_atomically<T: Serialize>(path: &Path, value: &T) -> Result<(), anyhow::Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, serde_json::to_vec(value)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Number of input lines read and parsed by one parse task.
+const CHUNK_LINES: usize = 1024;
+
+/// A chunk of input lines, parsed by a parse task.
+struct Chunk {
+    /// Number of the last line of the chunk, counted from 1.
+    last_line: u64,
+    /// Number of documents not parsed because the checkpoint has them acknowledged.
+    skipped: u64,
+    /// The other documents.
+    docs: Vec<ParsedDoc>,
+    /// Time spent parsing the chunk.
+    parse_time: Duration,
+    /// Number of documents whose text was cut to `--max-text-bytes`.
+    truncated: u64,
+    /// Number of documents with fields dropped by `--keep-fields`.
+    stripped: u64,
+    /// Number of documents given an id by `--generate-ids`.
+    generated_ids: u64,
+}
+
+/// A document parsed and serialized again as a row by a parse task, so that the ingest loop and
+/// the upload tasks only move bytes around.
+struct ParsedDoc {
+    line_number: u64,
+    /// Size of the line in the input.
+    input_bytes: usize,
+    id: String,
+    /// Hash of the id, to detect duplicate ids without keeping them all in memory.
+    id_hash: u64,
+    shard: usize,
+    row: Box<RawValue>,
+}
+
+/// What the parse tasks do besides parsing the documents.
+struct ParseParams {
+    /// Number of input lines acknowledged according to the checkpoint.
+    skip_lines: u64,
+    shards: usize,
+    /// The selectivities of the synthetic `filter` tags, with `--synthetic-filters`.
+    synthetic_filters: Option<Vec<f64>>,
+    /// Whether to add the synthetic attributes, with `--synthetic-attributes`.
+    synthetic_attributes: bool,
+    title_field: Option<String>,
+    vector_field: Option<String>,
+    /// Columns of the id and the text, if the input is TSV rather than JSON lines.
+    tsv_columns: Option<(usize, usize)>,
+    keep_fields: Option<Vec<String>>,
+    max_text_bytes: Option<usize>,
+    generate_ids: IdGeneration,
+}
+
+/// What the `--probe-query` counts saw, as times since the start of the ingest.
+#[derive(Default)]
+struct Probe {
+    first_results: Option<Duration>,
+    last_change: Option<Duration>,
+    count: u64,
+}
+
+/// Parameters sent along with every upsert request.
+struct WriteParams {
+    schema: serde_json::Value,
+    distance_metric: Option<String>,
+    columnar: bool,
+    log: Option<IngestLog>,
+}
+
+/// The `--ingest-log` file.
+struct IngestLog {
+    start: Instant,
+    file: Mutex<std::fs::File>,
+}
+
+impl IngestLog {
+    fn create(path: &Path) -> Result<IngestLog, anyhow::Error> {
+        Ok(IngestLog {
+            start: Instant::now(),
+            file: Mutex::new(std::fs::File::create(path)?),
+        })
+    }
+
+    /// Appends `record` with the seconds elapsed since the start of the ingest. The log is
+    /// best-effort: failing to write it doesn't fail the ingest.
+    fn record(&self, mut record: serde_json::Value) {
+        record["elapsed_secs"] = self.start.elapsed().as_secs_f64().into();
+        let line = format!("{record}\n");
+        if let Err(err) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            eprintln!("failed to write the ingest log: {err}");
+        }
+    }
+}
+
+/// Languages supported by turbopuffer's full-text search.
+const LANGUAGES: [&str; 18] = [
+    "arabic",
+    "danish",
+    "dutch",
+    "english",
+    "finnish",
+    "french",
+    "german",
+    "greek",
+    "hungarian",
+    "italian",
+    "norwegian",
+    "portuguese",
+    "romanian",
+    "russian",
+    "spanish",
+    "swedish",
+    "tamil",
+    "turkish",
+];
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let api_keys = ApiKeys::from_env(Access::ReadWrite)?;
+    let started_at = manifest::unix_time();
     env_logger::init();
+    if args.parse_threads == 0 {
+        anyhow::bail!("--parse-threads must be positive");
+    }
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = args.metrics_addr {
+        turbopuffer_bench::metrics::serve(addr).await?;
+    }
 
-    if let Ok(_) = delete_namespace().await {
-        println!("namespace {NAMESPACE} deleted");
+    let clients: Arc<[Client]> = Client::new(&args.client, &api_keys, args.max_attempts)?
+        .with_compression(args.compression)
+        .shards(usize::from(args.shards))
+        .into();
+    let with_vectors = args.vector_field.is_some() || args.vectors.is_some();
+    let params = Arc::new(WriteParams {
+        schema: schema(&args)?,
+        distance_metric: with_vectors.then(|| args.distance_metric.clone()),
+        columnar: args.columnar,
+        log: args
+            .ingest_log
+            .as_deref()
+            .map(IngestLog::create)
+            .transpose()?,
+    });
+    let mut vectors = match &args.vectors {
+        Some(path) => Some(std::io::BufReader::new(std::fs::File::open(path)?).lines()),
+        None => None,
+    };
+
+    let limits = Limits {
+        writes: args.max_writes_per_sec.map(RateLimiter::new),
+        bytes: args.max_mb_per_sec.map(|rate| RateLimiter::new(rate * 1e6)),
+    };
+    let mut checkpoint = Checkpoint::new(args.checkpoint.clone(), &args.client.namespace);
+    let skip_lines = if args.resume {
+        let lines = checkpoint.load()?;
+        println!("resuming after line {lines}");
+        lines
     } else {
-        println!("namespace {NAMESPACE} not found, ignoring");
-    }
+        for client in clients.iter() {
+            if client.delete_namespace().await.is_ok() {
+                println!("namespace {} deleted", client.namespace());
+            } else {
+                println!("namespace {} not found, ignoring", client.namespace());
+            }
+        }
+        0
+    };
+
+    let input = open_input(&args)?;
 
+    let ingest_start = Instant::now();
+    let probe = Arc::new(Mutex::new(Probe::default()));
+    let prober = args.probe_query.as_ref().map(|query| {
+        tokio::spawn(run_probe(
+            clients.clone(),
+            query.clone(),
+            Duration::from_secs_f64(args.probe_interval),
+            ingest_start,
+            probe.clone(),
+        ))
+    });
+    let progress = Arc::new(Progress::new(input.len));
+    let reporter = progress.spawn_reporter(Duration::from_secs(args.progress_interval.max(1)));
     let mut join_set = JoinSet::new();
+    let mut concurrency = Concurrency::new(&args);
+    let mut in_flight_bytes = InFlightBytes::new(&args);
     let mut i = 0;
-    let mut batch = vec![];
+    let mut batch = Vec::with_capacity(args.batch_size as usize);
+    let mut batch_bytes = 0;
+    let mut batch_start = skip_lines;
+    let mut line_number = 0;
+    let mut written = Written::default();
+
+    // Time spent by the parse tasks, and time the ingest loop waited for them, respectively for
+    // the uploads: the larger wait tells whether parsing or uploading is the bottleneck.
+    let mut parsed_bytes = 0;
+    let mut parse_time = Duration::ZERO;
+    let mut parse_wait = Duration::ZERO;
+    let mut upload_wait = Duration::ZERO;
+    let mut truncated = 0;
+    let mut stripped = 0;
+    let mut generated_ids = 0;
+    // Hashes of the ids read, and the number and first few of the ids read more than once.
+    let mut ids = HashSet::new();
+    let mut duplicate_ids = 0;
+    let mut duplicate_examples = vec![];
+
+    let parse_params = ParseParams {
+        skip_lines,
+        shards: clients.len(),
+        synthetic_filters: args
+            .synthetic_filters
+            .then(|| args.filter_selectivities.clone()),
+        synthetic_attributes: args.synthetic_attributes,
+        title_field: args.title_field.clone(),
+        vector_field: args.vector_field.clone(),
+        tsv_columns: (args.format == InputFormat::Tsv).then_some((args.id_col, args.text_col)),
+        keep_fields: args.keep_fields.clone(),
+        max_text_bytes: args.max_text_bytes,
+        generate_ids: args.generate_ids,
+    };
+    let mut chunks = parse_input(input.lines, parse_params, args.parse_threads);
+    let interrupted = watch_interrupts();
+    loop {
+        if interrupted.load(Ordering::Relaxed) {
+            break;
+        }
+        let waiting = Instant::now();
+        let Some(chunk) = chunks.recv().await else {
+            break;
+        };
+        let chunk = chunk.await??;
+        parse_wait += waiting.elapsed();
+        parse_time += chunk.parse_time;
+        truncated += chunk.truncated;
+        stripped += chunk.stripped;
+        generated_ids += chunk.generated_ids;
+        // Keep the vectors file aligned with the documents.
+        if let Some(vectors) = &mut vectors {
+            for _ in 0..chunk.skipped {
+                vectors.next().transpose()?;
+            }
+        }
+        for mut doc in chunk.docs {
+            line_number = doc.line_number;
+            i += 1;
+            progress.read(doc.input_bytes);
+            parsed_bytes += doc.input_bytes;
+            if !ids.insert(doc.id_hash) {
+                duplicate_ids += 1;
+                if duplicate_examples.len() < 10 {
+                    duplicate_examples.push(doc.id.clone());
+                }
+            }
+            if let Some(vectors) = &mut vectors {
+                let Some(vector) = vectors.next() else {
+                    anyhow::bail!("vectors file has fewer lines than documents");
+                };
+                doc.row = with_vector(&doc.row, &vector?)?;
+            }
+            let doc_bytes = doc.row.get().len();
+            let batch_full = batch.len() >= args.batch_size as usize
+                || (!batch.is_empty() && batch_bytes + doc_bytes > args.max_batch_bytes);
+            if batch_full {
+                limits.wait(batch_bytes).await;
+                join_set.spawn(write_batch(
+                    clients.clone(),
+                    params.clone(),
+                    progress.clone(),
+                    batch_start..line_number - 1,
+                    mem::replace(&mut batch, Vec::with_capacity(args.batch_size as usize)),
+                ));
+                concurrency.sent(batch_start);
+                in_flight_bytes.sent(batch_start, batch_bytes);
+                batch_bytes = 0;
+                batch_start = line_number - 1;
+            }
+            batch.push(doc);
+            batch_bytes += doc_bytes;
+            while join_set.len() >= concurrency.limit()
+                || (!join_set.is_empty() && in_flight_bytes.over_budget(batch_bytes))
+            {
+                let waiting = Instant::now();
+                let (lines, batch_written) = join_set.join_next().await.unwrap()??;
+                upload_wait += waiting.elapsed();
+                concurrency.completed(&lines, &batch_written);
+                in_flight_bytes.completed(&lines);
+                written.merge(batch_written);
+                checkpoint.acknowledge(lines)?;
+            }
+            progress.set_in_flight(join_set.len());
+        }
+        line_number = chunk.last_line;
+    }
+    let end = line_number.max(skip_lines);
+    if batch.is_empty() {
+        checkpoint.acknowledge(batch_start..end)?;
+    } else {
+        while !join_set.is_empty() && in_flight_bytes.over_budget(batch_bytes) {
+            let (lines, batch_written) = join_set.join_next().await.unwrap()??;
+            concurrency.completed(&lines, &batch_written);
+            in_flight_bytes.completed(&lines);
+            written.merge(batch_written);
+            checkpoint.acknowledge(lines)?;
+        }
+        limits.wait(batch_bytes).await;
+        join_set.spawn(write_batch(
+            clients.clone(),
+            params.clone(),
+            progress.clone(),
+            batch_start..end,
+            mem::take(&mut batch),
+        ));
+        concurrency.sent(batch_start);
+        in_flight_bytes.sent(batch_start, batch_bytes);
+    }
+
+    let waiting = Instant::now();
+    while let Some(result) = join_set.join_next().await {
+        let (lines, batch_written) = result??;
+        concurrency.completed(&lines, &batch_written);
+        in_flight_bytes.completed(&lines);
+        written.merge(batch_written);
+        checkpoint.acknowledge(lines)?;
+    }
+    upload_wait += waiting.elapsed();
+    reporter.abort();
+    let acknowledged = written.rows_affected;
+    println!("{i} documents read, {acknowledged} acknowledged by the server");
+    if let Some(max_text_bytes) = args.max_text_bytes {
+        println!("{truncated} documents with a text truncated to {max_text_bytes} bytes");
+    }
+    if args.keep_fields.is_some() {
+        println!("{stripped} documents with fields dropped by --keep-fields");
+    }
+    if generated_ids > 0 {
+        println!("{generated_ids} documents without an id given one by --generate-ids");
+    }
+    if duplicate_ids > 0 {
+        eprintln!(
+            "{duplicate_ids} documents overwrite an earlier document with the same id, e.g. {}",
+            duplicate_examples.join(", ")
+        );
+    }
+    if args.compression != Compression::None {
+        println!(
+            "compression: {:.1} MB of requests sent as {:.1} MB, ratio {:.2}",
+            written.body_bytes as f64 / 1e6,
+            written.sent_bytes as f64 / 1e6,
+            written.body_bytes as f64 / written.sent_bytes.max(1) as f64,
+        );
+    }
+    if let Some(budget) = in_flight_bytes.budget {
+        println!(
+            "in flight: at most {:.1} MB of documents, for a budget of {:.1} MB",
+            in_flight_bytes.peak as f64 / 1e6,
+            budget as f64 / 1e6,
+        );
+    }
+    if args.adaptive_concurrency && concurrency.completed_batches > 0 {
+        println!(
+            "adaptive concurrency: settled at {} requests in flight, {:.1} on average, after {} \
+             decreases on retries or slow requests",
+            concurrency.limit(),
+            concurrency.limit_sum / concurrency.completed_batches as f64,
+            concurrency.decreases,
+        );
+    }
+    if acknowledged != i {
+        anyhow::bail!("{i} documents were read but only {acknowledged} were acknowledged");
+    }
+    if interrupted.load(Ordering::Relaxed) {
+        match &args.checkpoint {
+            Some(path) => println!(
+                "interrupted after line {end}, all documents up to it acknowledged; continue with \
+                 --checkpoint {} --resume",
+                path.display()
+            ),
+            None => println!(
+                "interrupted after line {end}, all documents up to it acknowledged; run with \
+                 --checkpoint to be able to continue an interrupted ingest with --resume"
+            ),
+        }
+        anyhow::bail!("ingest interrupted");
+    }
+    let ingest = ingest_start.elapsed();
+    println!(
+        "parse: {:.1} MB in {:.1}s of parse time, {:.1} MB/s per thread; waited {:.1}s for parsing",
+        parsed_bytes as f64 / 1e6,
+        parse_time.as_secs_f64(),
+        parsed_bytes as f64 / 1e6 / parse_time.as_secs_f64().max(1e-9),
+        parse_wait.as_secs_f64(),
+    );
+    println!(
+        "upload: {:.1} MB of requests in {:.1}s, {:.1} MB/s; waited {:.1}s for uploads",
+        written.body_bytes as f64 / 1e6,
+        ingest.as_secs_f64(),
+        written.body_bytes as f64 / 1e6 / ingest.as_secs_f64().max(1e-9),
+        upload_wait.as_secs_f64(),
+    );
 
-    let stdin = std::io::stdin();
-    for line in stdin.lock().lines() {
+    let poll_interval = Duration::from_secs_f64(args.poll_interval);
+    let deadline = args
+        .index_timeout
+        .map(|timeout| Instant::now() + Duration::from_secs_f64(timeout));
+    for client in clients.iter() {
+        wait_for_index(client, poll_interval, deadline).await?;
+    }
+    let index_ready = ingest_start.elapsed() - ingest;
+    if let Some(prober) = prober {
+        prober.abort();
+    }
+    let probe = mem::take(&mut *probe.lock().unwrap());
+    if args.probe_query.is_some() {
+        match (probe.first_results, probe.last_change) {
+            (Some(first_results), Some(last_change)) => println!(
+                "probe: first results after {:.1}s, count stable at {} after {:.1}s",
+                first_results.as_secs_f64(),
+                probe.count,
+                last_change.as_secs_f64(),
+            ),
+            _ => println!("probe: no results before the index was up to date"),
+        }
+    }
+
+    let mut approx_row_count = 0;
+    let mut approx_logical_bytes = 0;
+    for client in clients.iter() {
+        let metadata = client.metadata().await?;
+        approx_row_count += metadata.approx_row_count;
+        approx_logical_bytes += metadata.approx_logical_bytes;
+    }
+    // One JSON line, for the harness to compare the build cost across engines.
+    let summary = serde_json::json!({
+        "namespace": args.client.namespace,
+        "shards": args.shards,
+        "columnar": args.columnar,
+        "concurrency": concurrency.limit(),
+        "documents": acknowledged,
+        "ingest_secs": ingest.as_secs_f64(),
+        "parse_secs": parse_time.as_secs_f64(),
+        "parse_wait_secs": parse_wait.as_secs_f64(),
+        "upload_wait_secs": upload_wait.as_secs_f64(),
+        "duplicate_ids": duplicate_ids,
+        "probe_first_results_secs": probe.first_results.map(|time| time.as_secs_f64()),
+        "probe_stable_secs": probe.last_change.map(|time| time.as_secs_f64()),
+        "index_ready_secs": index_ready.as_secs_f64(),
+        "total_secs": (ingest + index_ready).as_secs_f64(),
+        "request_bytes": written.body_bytes,
+        "approx_row_count": approx_row_count,
+        "approx_logical_bytes": approx_logical_bytes,
+    });
+    println!("summary: {summary}");
+    let bm25 = &params.schema["text"]["full_text_search"];
+    manifest::record(
+        &args.run_manifest,
+        "build_index",
+        serde_json::json!({
+            "started_at": started_at,
+            "finished_at": manifest::unix_time(),
+            "input": args.input,
+            "corpus_lines": end,
+            "schema": params.schema,
+            "bm25": {"k1": bm25.get("k1"), "b": bm25.get("b")},
+            "synthetic_filters": args.synthetic_filters.then_some(&args.filter_selectivities),
+            "synthetic_attributes": args.synthetic_attributes,
+            "batch_size": args.batch_size,
+            "max_batch_bytes": args.max_batch_bytes,
+            "concurrency": args.concurrency,
+            "adaptive_concurrency": args.adaptive_concurrency,
+            "summary": summary,
+        }),
+    )?;
+
+    otlp::flush().await;
+    Ok(())
+}
+
+fn schema(args: &Args) -> Result<serde_json::Value, anyhow::Error> {
+    if let Some(path) = &args.schema {
+        let schema: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|err| anyhow::anyhow!("invalid schema {}: {err}", path.display()))?;
+        if !schema.is_object() {
+            anyhow::bail!("invalid schema {}: expected a JSON object", path.display());
+        }
+        return Ok(schema);
+    }
+    let full_text_search = serde_json::json!({
+        "language": args.language,
+        "remove_stopwords": args.remove_stopwords,
+        "stemming": args.stemming,
+        "case_sensitive": args.case_sensitive,
+        "tokenizer": args.tokenizer,
+        "k1": args.bm25_k1,
+        "b": args.bm25_b,
+    });
+    let mut schema = serde_json::json!({
+        "id": "string",
+        "text": {
+            "type": "string",
+            "full_text_search": full_text_search,
+        },
+        "filter": {
+            "type": "[]string",
+        },
+    });
+    if args.synthetic_attributes {
+        corpus::add_synthetic_schema(&mut schema);
+    }
+    if args.title_field.is_some() {
+        schema[corpus::TITLE_ATTRIBUTE] = serde_json::json!({
+            "type": "string",
+            "full_text_search": full_text_search,
+        });
+    }
+    Ok(schema)
+}
+
+fn open_input(args: &Args) -> Result<Input, anyhow::Error> {
+    match (args.format, &args.input) {
+        (InputFormat::Jsonl | InputFormat::Tsv, Some(path)) => {
+            let file = std::fs::File::open(path)?;
+            let len = file.metadata()?.len();
+            Ok(Input {
+                lines: Box::new(std::io::BufReader::with_capacity(1 << 20, file)),
+                len: Some(len),
+            })
+        }
+        (InputFormat::Jsonl | InputFormat::Tsv, None) => Ok(Input {
+            lines: Box::new(std::io::BufReader::new(std::io::stdin())),
+            len: stdin_len(),
+        }),
+        (InputFormat::Parquet, Some(path)) => open_parquet(args, path),
+        (InputFormat::Parquet, None) => anyhow::bail!("--format parquet requires --input"),
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn open_parquet(args: &Args, path: &Path) -> Result<Input, anyhow::Error> {
+    use turbopuffer_bench::parquet_input::{ColumnMapping, ParquetLines};
+    let mapping = ColumnMapping {
+        id: args.id_column.clone(),
+        text: args.text_column.clone(),
+        filter: args.filter_column.clone(),
+        other: args
+            .title_field
+            .iter()
+            .chain(&args.vector_field)
+            .cloned()
+            .collect(),
+    };
+    Ok(Input {
+        lines: Box::new(std::io::BufReader::new(ParquetLines::open(path, mapping)?)),
+        // The size of the file says little about the size of its rows as JSON.
+        len: None,
+    })
+}
+
+#[cfg(not(feature = "parquet"))]
+fn open_parquet(_: &Args, _: &Path) -> Result<Input, anyhow::Error> {
+    anyhow::bail!("--format parquet requires building with --features parquet")
+}
+
+/// Reads `input` on a blocking thread and parses it in chunks of [`CHUNK_LINES`] lines on tokio's
+/// blocking pool, with about `parse_threads` chunks parsed or waiting to be consumed at a time.
+/// The parse tasks are yielded in input order, so that line numbers and the vectors file stay
+/// aligned with the documents.
+fn parse_input(
+    input: Box<dyn BufRead + Send>,
+    params: ParseParams,
+    parse_threads: usize,
+) -> mpsc::Receiver<JoinHandle<Result<Chunk, anyhow::Error>>> {
+    let (sender, receiver) = mpsc::channel(parse_threads);
+    let params = Arc::new(params);
+    let runtime = tokio::runtime::Handle::current();
+    runtime.clone().spawn_blocking(move || {
+        let mut lines = input.lines();
+        let mut first_line = 1;
+        loop {
+            let chunk: Vec<_> = lines.by_ref().take(CHUNK_LINES).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            // A read error ends the input, the parse task reports it.
+            let last = chunk.len() < CHUNK_LINES || chunk.iter().any(Result::is_err);
+            let params = params.clone();
+            let task = runtime.spawn_blocking(move || parse_chunk(first_line, chunk, &params));
+            first_line += CHUNK_LINES as u64;
+            // Sending fails once the ingest loop has given up.
+            if sender.blocking_send(task).is_err() || last {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Parses the lines of a chunk starting at line `first_line`, counted from 1.
+fn parse_chunk(
+    first_line: u64,
+    lines: Vec<std::io::Result<String>>,
+    params: &ParseParams,
+) -> Result<Chunk, anyhow::Error> {
+    let start = Instant::now();
+    let mut chunk = Chunk {
+        last_line: first_line + lines.len() as u64 - 1,
+        skipped: 0,
+        docs: Vec::with_capacity(lines.len()),
+        parse_time: Duration::ZERO,
+        truncated: 0,
+        stripped: 0,
+        generated_ids: 0,
+    };
+    for (line_number, line) in (first_line..).zip(lines) {
         let line = line?;
         if line.trim().is_empty() {
             continue;
         }
-        i += 1;
-        if i % 100_000 == 0 {
-            println!("{}", i);
+        if line_number <= params.skip_lines {
+            chunk.skipped += 1;
+            continue;
         }
-        let doc = serde_json::from_str(&line)?;
-        batch.push(doc);
-        if batch.len() >= BATCH_SIZE {
-            join_set.spawn(write_batch(mem::take(&mut batch)));
+        let doc = match params.tsv_columns {
+            Some((id_col, text_col)) => tsv_document(&line, id_col, text_col),
+            None => serde_json::from_str(&line).map_err(anyhow::Error::from),
+        };
+        let mut doc = doc.map_err(|err| anyhow::anyhow!("line {line_number}: {err}"))?;
+        if doc.id.is_empty() {
+            doc.id = match params.generate_ids {
+                IdGeneration::None => anyhow::bail!("line {line_number}: document has no id"),
+                IdGeneration::Sequential => line_number.to_string(),
+                IdGeneration::Uuid => uuid(line_number),
+            };
+            chunk.generated_ids += 1;
         }
-        if join_set.len() >= MAX_CONCURRENCY {
-            let _ = join_set.join_next().await.unwrap()?;
+        if params
+            .max_text_bytes
+            .is_some_and(|max_bytes| truncate(&mut doc.text, max_bytes))
+        {
+            chunk.truncated += 1;
         }
+        corpus::augment(
+            &mut doc,
+            params.synthetic_filters.as_deref(),
+            params.synthetic_attributes,
+        );
+        if let Some(field) = &params.title_field {
+            // A `title` field is parsed into the title already.
+            if let Some(title) = doc.extra.remove(field) {
+                doc.title = Some(serde_json::from_value(title)?);
+            }
+            if doc.title.is_none() {
+                anyhow::bail!("line {line_number}: document has no {field:?} field");
+            }
+        }
+        if let Some(field) = &params.vector_field {
+            let Some(vector) = doc.extra.remove(field) else {
+                anyhow::bail!("line {line_number}: document has no {field:?} field");
+            };
+            doc.vector = Some(serde_json::from_value(vector)?);
+        }
+        if let Some(keep_fields) = &params.keep_fields {
+            let fields = doc.extra.len();
+            doc.extra.retain(|field, _| keep_fields.contains(field));
+            if doc.extra.len() < fields {
+                chunk.stripped += 1;
+            }
+        }
+        let mut hasher = DefaultHasher::new();
+        doc.id.hash(&mut hasher);
+        chunk.docs.push(ParsedDoc {
+            line_number,
+            // Count the newline stripped by `lines` towards the progress through the input.
+            input_bytes: line.len() + 1,
+            shard: corpus::shard(&doc.id, params.shards),
+            id_hash: hasher.finish(),
+            row: serde_json::value::to_raw_value(&doc)?,
+            id: doc.id,
+        });
     }
-    if !batch.is_empty() {
-        join_set.spawn(write_batch(mem::take(&mut batch)));
-    }
+    chunk.parse_time = start.elapsed();
+    Ok(chunk)
+}
+
+/// A version 4 UUID derived from `line_number`.
+fn uuid(line_number: u64) -> String {
+    let mut rng = SplitMix64::new(line_number);
+    let mut bits = (u128::from(rng.next_u64()) << 64) | u128::from(rng.next_u64());
+    // The version and variant fields.
+    bits = (bits & !(0xf << 76)) | (0x4 << 76);
+    bits = (bits & !(0x3 << 62)) | (0x2 << 62);
+    let hex = format!("{bits:032x}");
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
 
-    for result in join_set.join_all().await {
-        result?;
+/// Truncates `text` to at most `max_bytes` bytes at a character boundary, returning whether it was
+/// longer.
+fn truncate(text: &mut String, max_bytes: usize) -> bool {
+    if text.len() <= max_bytes {
+        return false;
     }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    true
+}
 
-    wait_for_index().await?;
+/// The document of a TSV line, with the id and the text in the columns `id_col` and `text_col`.
+fn tsv_document(line: &str, id_col: usize, text_col: usize) -> Result<Document, anyhow::Error> {
+    let columns: Vec<&str> = line.split('\t').collect();
+    let (Some(id), Some(text)) = (columns.get(id_col), columns.get(text_col)) else {
+        anyhow::bail!(
+            "expected at least {} tab-separated columns, got {}",
+            id_col.max(text_col) + 1,
+            columns.len()
+        );
+    };
+    Ok(Document {
+        id: id.to_string(),
+        text: text.to_string(),
+        ..Document::default()
+    })
+}
 
-    Ok(())
+/// The size of stdin if it is redirected from a file, used to estimate the remaining time.
+#[cfg(unix)]
+fn stdin_len() -> Option<u64> {
+    use std::os::fd::AsFd;
+    let stdin = std::fs::File::from(std::io::stdin().as_fd().try_clone_to_owned().ok()?);
+    let metadata = stdin.metadata().ok()?;
+    metadata.is_file().then_some(metadata.len())
 }
 
-async fn delete_namespace() -> Result<(), anyhow::Error> {
-    let client = reqwest::Client::new();
-    client
-        .delete(format!("{API_URL}/v1/namespaces/{NAMESPACE}"))
-        .header("Authorization", format!("Bearer {}", API_KEY.as_str()))
-        .send()
-        .await?
-        .error_for_status()?;
-    Ok(())
+#[cfg(not(unix))]
+fn stdin_len() -> Option<u64> {
+    None
+}
+
+/// Appends a `vector` attribute to `row`, splicing in the JSON array `vector` as is.
+fn with_vector(row: &RawValue, vector: &str) -> Result<Box<RawValue>, anyhow::Error> {
+    let vector: &RawValue = serde_json::from_str(vector)?;
+    if !vector.get().starts_with('[') {
+        anyhow::bail!("expected one JSON array per line in the vectors file");
+    }
+    // Rows are serialized documents: objects with an id and without a vector.
+    let head = row.get().strip_suffix('}').expect("rows are JSON objects");
+    let row = RawValue::from_string(format!("{head},\"vector\":{}}}", vector.get()))?;
+    Ok(row)
 }
 
-async fn write_batch(batch: Vec<serde_json::Value>) -> Result<(), anyhow::Error> {
-    let client = reqwest::Client::new();
-    client
-        .post(format!("{API_URL}/v2/namespaces/{NAMESPACE}"))
-        .header("Authorization", format!("Bearer {}", API_KEY.as_str()))
-        .json(&serde_json::json!({
-            "upsert_rows": batch,
-            "schema": {
-                "id": "string",
-                "text": {
-                    "type": "string",
-                    "full_text_search": {
-                        "remove_stopwords": false,
-                        "k1": 0.9,
-                        "b": 0.4,
+async fn write_batch(
+    clients: Arc<[Client]>,
+    params: Arc<WriteParams>,
+    progress: Arc<Progress>,
+    lines: Range<u64>,
+    batch: Vec<ParsedDoc>,
+) -> Result<(Range<u64>, Written), anyhow::Error> {
+    let documents = batch.len();
+    let mut shards: Vec<Vec<Box<RawValue>>> = (0..clients.len()).map(|_| vec![]).collect();
+    for doc in batch {
+        shards[doc.shard].push(doc.row);
+    }
+    let mut requests = JoinSet::new();
+    for (index, (client, shard)) in clients.iter().zip(shards).enumerate() {
+        if shard.is_empty() {
+            continue;
+        }
+        let client = client.clone();
+        let params = params.clone();
+        let lines = lines.clone();
+        requests.spawn(async move {
+            let schema = Some(&params.schema);
+            let distance_metric = params.distance_metric.as_deref();
+            let rows = shard.len();
+            let start = Instant::now();
+            let result = if params.columnar {
+                // Transposing a large batch takes a while, keep it off the async workers.
+                let columns = tokio::task::spawn_blocking(move || transpose(&shard)).await??;
+                client
+                    .upsert_columns(&columns, rows, schema, distance_metric)
+                    .await
+            } else {
+                client.upsert_rows(&shard, schema, distance_metric).await
+            };
+            let latency = start.elapsed();
+            if let Some(log) = &params.log {
+                let mut record = serde_json::json!({
+                    "shard": index,
+                    "lines": [lines.start, lines.end],
+                    "documents": rows,
+                    "latency_ms": latency.as_secs_f64() * 1e3,
+                });
+                match &result {
+                    Ok(response) => {
+                        record["status"] = response.body.status.into();
+                        record["attempts"] = response.attempts.into();
+                        record["rows_affected"] = response.body.rows_affected.into();
+                        record["body_bytes"] = response.body.body_bytes.into();
+                        record["sent_bytes"] = response.body.sent_bytes.into();
+                    }
+                    Err(err) => {
+                        let status = err
+                            .downcast_ref::<reqwest::Error>()
+                            .and_then(reqwest::Error::status);
+                        record["status"] = status.map(|status| status.as_u16()).into();
+                        record["error"] = err.to_string().into();
                     }
-                },
-                "filter": {
-                    "type": "[]string",
                 }
-            },
-            "disable_backpressure": true,
-        }))
-        .send()
-        .await?
-        .error_for_status()?;
-    println!("batch written");
-    Ok(())
+                log.record(record);
+            }
+            result.map(|response| (response, latency))
+        });
+    }
+    let mut written = Written::default();
+    while let Some(response) = requests.join_next().await {
+        let (response, latency) = response??;
+        if response.attempts > 1 {
+            written.retried_requests += 1;
+        }
+        written.latency += latency;
+        written.add(response.body);
+    }
+    progress.acknowledged(written.rows_affected);
+    METRICS.documents_indexed(written.rows_affected);
+    if written.rows_affected != documents as u64 {
+        eprintln!(
+            "batch of {documents} documents written, but only {} acknowledged",
+            written.rows_affected
+        );
+    }
+    Ok((lines, written))
 }
 
-async fn wait_for_index() -> Result<(), anyhow::Error> {
-    #[derive(Deserialize)]
-    struct MetadataResponse {
-        index: IndexStatus,
+/// One array of raw JSON values per attribute, `None` for the rows without the attribute.
+type Columns = BTreeMap<String, Vec<Option<Box<RawValue>>>>;
+
+/// Transposes `rows`, serialized JSON objects, into [`Columns`].
+fn transpose(rows: &[Box<RawValue>]) -> Result<Columns, anyhow::Error> {
+    let mut columns = Columns::new();
+    for (i, row) in rows.iter().enumerate() {
+        // Only the structure of the row is parsed, values stay raw JSON.
+        let attributes: BTreeMap<String, Box<RawValue>> = serde_json::from_str(row.get())?;
+        for (name, value) in attributes {
+            let column = columns
+                .entry(name)
+                .or_insert_with(|| vec![None; rows.len()]);
+            column[i] = Some(value);
+        }
+    }
+    Ok(columns)
+}
+
+/// Counts the documents of all shards containing any term of `query` every `interval`, recording
+/// the changes in `probe`, until aborted.
+async fn run_probe(
+    clients: Arc<[Client]>,
+    query: String,
+    interval: Duration,
+    start: Instant,
+    probe: Arc<Mutex<Probe>>,
+) {
+    let body = serde_json::json!({
+        "aggregate_by": {
+            "count": ["Count"],
+        },
+        "filters": ["text", "ContainsAnyToken", query],
+        "consistency": {"level": "eventual"},
+    });
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let count = match probe_count(&clients, &body).await {
+            Ok(count) => count,
+            Err(err) => {
+                eprintln!("probe query failed: {err}");
+                continue;
+            }
+        };
+        let elapsed = start.elapsed();
+        let mut probe = probe.lock().unwrap();
+        if count > 0 && probe.first_results.is_none() {
+            probe.first_results = Some(elapsed);
+        }
+        if count != probe.count {
+            probe.count = count;
+            probe.last_change = Some(elapsed);
+        }
     }
+}
 
-    #[derive(Deserialize)]
-    struct IndexStatus {
-        status: String,
-        unindexed_bytes: Option<usize>,
+async fn probe_count(clients: &[Client], body: &serde_json::Value) -> Result<u64, anyhow::Error> {
+    let mut count = 0;
+    for client in clients {
+        let response = client.query::<AggregationResponse>(body).await?;
+        count += response.body.aggregations["count"];
     }
+    Ok(count)
+}
 
+/// Polls the index status every `poll_interval` until it is up to date, or fails once
+/// `deadline` has passed.
+async fn wait_for_index(
+    client: &Client,
+    poll_interval: Duration,
+    deadline: Option<Instant>,
+) -> Result<(), anyhow::Error> {
     loop {
-        let client = reqwest::Client::new();
-        let response = client
-            .get(format!("{API_URL}/v1/namespaces/{NAMESPACE}/metadata"))
-            .header("Authorization", format!("Bearer {}", API_KEY.as_str()))
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<MetadataResponse>()
-            .await?;
-        if response.index.status == "up-to-date" {
+        let metadata = client.metadata().await?;
+        if metadata.index.status == "up-to-date" {
             println!("index up-to-date");
             return Ok(());
         } else {
             println!(
                 "index not up-to-date; unindexed bytes: {}",
-                response.index.unindexed_bytes.unwrap()
+                metadata.index.unindexed_bytes.unwrap()
             );
         }
-        tokio::time::sleep(Duration::from_secs(10)).await;
+        let sleep = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    anyhow::bail!(
+                        "index of namespace {} not up-to-date after --index-timeout",
+                        client.namespace()
+                    );
+                }
+                poll_interval.min(remaining)
+            }
+            None => poll_interval,
+        };
+        tokio::time::sleep(sleep).await;
+    }
+}
+
+fn parse_rate(value: &str) -> Result<f64, anyhow::Error> {
+    let rate: f64 = value.parse()?;
+    if !(rate > 0.0 && rate.is_finite()) {
+        anyhow::bail!("rate must be a positive number");
+    }
+    Ok(rate)
+}
+
+fn parse_seconds(value: &str) -> Result<f64, anyhow::Error> {
+    let seconds: f64 = value.parse()?;
+    if !(seconds > 0.0 && seconds.is_finite()) {
+        anyhow::bail!("duration must be a positive number of seconds");
+    }
+    Ok(seconds)
+}
+
+fn parse_k1(value: &str) -> Result<f64, anyhow::Error> {
+    let k1: f64 = value.parse()?;
+    if !(k1 >= 0.0 && k1.is_finite()) {
+        anyhow::bail!("k1 must be a non-negative number");
+    }
+    Ok(k1)
+}
+
+fn parse_b(value: &str) -> Result<f64, anyhow::Error> {
+    let b: f64 = value.parse()?;
+    if !(0.0..=1.0).contains(&b) {
+        anyhow::bail!("b must be between 0 and 1");
     }
+    Ok(b)
 }
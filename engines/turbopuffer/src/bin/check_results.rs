@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::PathBuf;
+
+use clap::Parser;
+use serde::Deserialize;
+use turbopuffer_bench::client::{Access, ApiKeys, Client, ClientArgs, QueryResponse};
+use turbopuffer_bench::query::{ParseOptions, Query};
+use turbopuffer_bench::{corpus, otlp};
+
+/// Replays a query file against turbopuffer and compares the top-k results with a golden file
+/// produced by an exhaustive reference engine.
+///
+/// Prints one tab-separated line per query with recall@10, recall@100 and the Kendall rank
+/// correlation of the documents found by both, followed by averages on stderr.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    client: ClientArgs,
+
+    /// Query file in the benchmark's JSONL format, e.g. `queries.txt`.
+    #[arg(long)]
+    queries: PathBuf,
+
+    /// JSONL file with one `{"query": ..., "ids": [...], "scores": [...]}` record per query, ids
+    /// in rank order. `scores` is optional.
+    #[arg(long)]
+    golden: PathBuf,
+
+    /// Command used to run every query. Its top_k should be at least 100 for recall@100.
+    #[arg(long, default_value = "TOP_100")]
+    command: String,
+
+    /// Selectivities, in percent, of the `filter` tags the namespace was indexed with. Other
+    /// `*_FILTER_<pct>%` commands are reported as unsupported.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = corpus::FILTER_SELECTIVITIES,
+        value_parser = corpus::parse_selectivity
+    )]
+    filter_selectivities: Vec<f64>,
+
+    /// The namespace was built with `--synthetic-attributes`, supporting the commands that
+    /// filter, sort or group by them.
+    #[arg(long)]
+    synthetic_attributes: bool,
+
+    /// Maximum number of attempts per query.
+    #[arg(long, default_value_t = 5)]
+    max_attempts: u32,
+}
+
+#[derive(Deserialize)]
+struct QueryRecord {
+    query: String,
+}
+
+#[derive(Deserialize)]
+struct GoldenRecord {
+    query: String,
+    ids: Vec<String>,
+    #[serde(default)]
+    scores: Vec<f64>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let api_keys = ApiKeys::from_env(Access::Read)?;
+    let client = Client::new(&args.client, &api_keys, args.max_attempts)?;
+    let options = ParseOptions {
+        filter_selectivities: args.filter_selectivities.clone(),
+        synthetic_attributes: args.synthetic_attributes,
+        ..ParseOptions::default()
+    };
+
+    let mut golden = HashMap::new();
+    for line in std::io::BufReader::new(std::fs::File::open(&args.golden)?).lines() {
+        let record: GoldenRecord = serde_json::from_str(&line?)?;
+        golden.insert(record.query.clone(), record);
+    }
+
+    let mut recall_10 = vec![];
+    let mut recall_100 = vec![];
+    let mut kendall_tau = vec![];
+    println!("query\trecall@10\trecall@100\tkendall_tau\ttop_score_delta");
+    for line in std::io::BufReader::new(std::fs::File::open(&args.queries)?).lines() {
+        let record: QueryRecord = serde_json::from_str(&line?)?;
+        let Some(expected) = golden.get(&record.query) else {
+            eprintln!("no golden results for {:?}, skipping", record.query);
+            continue;
+        };
+        let Some(query) = Query::parse(&args.command, &record.query, &options) else {
+            anyhow::bail!("unsupported command {:?}", args.command);
+        };
+        if query.count_only {
+            anyhow::bail!("{:?} does not return rows", args.command);
+        }
+        if query.page_size.is_some() {
+            anyhow::bail!("{:?} is paged, use the TOP_* command instead", args.command);
+        }
+        let response = client.query::<QueryResponse>(&query.body).await?.body;
+        let ids: Vec<String> = response.rows.iter().map(|row| row.id_string()).collect();
+
+        let r10 = recall(&expected.ids, &ids, 10);
+        let r100 = recall(&expected.ids, &ids, 100);
+        let tau = kendall(&expected.ids, &ids);
+        let top_score_delta = match (expected.scores.first(), response.rows.first()) {
+            (Some(expected), Some(row)) => row.dist.map(|dist| dist - expected),
+            _ => None,
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            record.query,
+            format_metric(r10),
+            format_metric(r100),
+            format_metric(tau),
+            format_metric(top_score_delta),
+        );
+        recall_10.extend(r10);
+        recall_100.extend(r100);
+        kendall_tau.extend(tau);
+    }
+
+    eprintln!(
+        "mean over {} queries: recall@10={} recall@100={} kendall_tau={}",
+        recall_10.len(),
+        format_metric(mean(&recall_10)),
+        format_metric(mean(&recall_100)),
+        format_metric(mean(&kendall_tau)),
+    );
+    otlp::flush().await;
+    Ok(())
+}
+
+/// Fraction of the expected top `k` found in the actual top `k`, `None` if nothing is expected.
+fn recall(expected: &[String], actual: &[String], k: usize) -> Option<f64> {
+    let expected = &expected[..expected.len().min(k)];
+    if expected.is_empty() {
+        return None;
+    }
+    let actual = &actual[..actual.len().min(k)];
+    let found = expected.iter().filter(|id| actual.contains(id)).count();
+    Some(found as f64 / expected.len() as f64)
+}
+
+/// Kendall's tau-a between both rankings, restricted to the documents present in both.
+fn kendall(expected: &[String], actual: &[String]) -> Option<f64> {
+    let actual_rank: HashMap<&String, usize> = actual
+        .iter()
+        .enumerate()
+        .map(|(rank, id)| (id, rank))
+        .collect();
+    // Ranks in `actual` of the common documents, in expected order.
+    let ranks: Vec<usize> = expected
+        .iter()
+        .filter_map(|id| actual_rank.get(id).copied())
+        .collect();
+    let n = ranks.len();
+    if n < 2 {
+        return None;
+    }
+    let mut concordant = 0i64;
+    let mut discordant = 0i64;
+    for i in 0..n {
+        for j in i + 1..n {
+            if ranks[i] < ranks[j] {
+                concordant += 1;
+            } else {
+                discordant += 1;
+            }
+        }
+    }
+    Some((concordant - discordant) as f64 / (n * (n - 1) / 2) as f64)
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+fn format_metric(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| format!("{value:.4}"))
+}
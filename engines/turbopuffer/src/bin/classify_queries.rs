@@ -0,0 +1,110 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use clap::Parser;
+use serde::Deserialize;
+use turbopuffer_bench::corpus::{Document, terms};
+use turbopuffer_bench::query::{ClassifiedQuery, CostClass};
+
+/// Counts the document frequencies of the query terms in the corpus read from stdin, and writes
+/// one JSON record per query to stdout with its cost class, for `do_query --query-classes`.
+///
+/// The cost of a query is the sum of the document frequencies of its distinct terms, i.e. the
+/// number of postings an engine may have to read, with terms split by [`terms`].
+#[derive(Parser)]
+struct Args {
+    /// Query file in the benchmark's JSONL format, e.g. `queries.txt`.
+    #[arg(long)]
+    queries: PathBuf,
+
+    /// Queries whose cost is at least this fraction of the number of documents are medium.
+    #[arg(long, default_value_t = 0.01)]
+    medium_threshold: f64,
+
+    /// Queries whose cost is at least this fraction of the number of documents are expensive.
+    #[arg(long, default_value_t = 0.1)]
+    expensive_threshold: f64,
+}
+
+#[derive(Deserialize)]
+struct QueryRecord {
+    query: String,
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    if args.medium_threshold > args.expensive_threshold {
+        anyhow::bail!("--medium-threshold must not exceed --expensive-threshold");
+    }
+
+    let mut queries = vec![];
+    for line in std::io::BufReader::new(std::fs::File::open(&args.queries)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: QueryRecord = serde_json::from_str(&line)?;
+        let terms = terms(&record.query).collect::<HashSet<_>>();
+        queries.push((record.query, terms));
+    }
+    // Only the frequencies of the query terms are counted, to keep memory bounded.
+    let mut document_frequencies: HashMap<String, u64> = queries
+        .iter()
+        .flat_map(|(_, terms)| terms.iter().map(|term| (term.clone(), 0)))
+        .collect();
+
+    let mut documents = 0u64;
+    let mut seen = HashSet::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let doc: Document = serde_json::from_str(&line)?;
+        documents += 1;
+        seen.clear();
+        for term in terms(&doc.text) {
+            if seen.contains(&term) {
+                continue;
+            }
+            if let Some(frequency) = document_frequencies.get_mut(&term) {
+                *frequency += 1;
+                seen.insert(term);
+            }
+        }
+    }
+    if documents == 0 {
+        anyhow::bail!("the corpus on stdin is empty");
+    }
+
+    let mut counts: BTreeMap<CostClass, usize> = BTreeMap::new();
+    let mut stdout = std::io::stdout().lock();
+    for (query, terms) in queries {
+        let postings: u64 = terms.iter().map(|term| document_frequencies[term]).sum();
+        let cost = postings as f64 / documents as f64;
+        let class = if cost >= args.expensive_threshold {
+            CostClass::Expensive
+        } else if cost >= args.medium_threshold {
+            CostClass::Medium
+        } else {
+            CostClass::Cheap
+        };
+        *counts.entry(class).or_default() += 1;
+        let record = ClassifiedQuery {
+            query,
+            class,
+            postings,
+        };
+        serde_json::to_writer(&mut stdout, &record)?;
+        writeln!(stdout)?;
+    }
+    stdout.flush()?;
+    eprintln!(
+        "{documents} documents: {} cheap, {} medium and {} expensive queries",
+        counts.get(&CostClass::Cheap).unwrap_or(&0),
+        counts.get(&CostClass::Medium).unwrap_or(&0),
+        counts.get(&CostClass::Expensive).unwrap_or(&0),
+    );
+    Ok(())
+}
@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use serde::Deserialize;
+use turbopuffer_bench::stats::mann_whitney_p;
+
+/// Compares the latencies of two `results.json` files written by the benchmark client, e.g.
+/// before and after a change to the index, and flags the commands that got slower.
+///
+/// For every command, the latencies of the queries found in both files are pooled and compared
+/// with the Mann-Whitney U test. A command regressed if its median latency grew by more than
+/// `--threshold` percent with a p-value below `--alpha`. Exits with an error if any did.
+#[derive(Parser)]
+struct Args {
+    /// The reference run.
+    baseline: PathBuf,
+
+    /// The run compared with the reference.
+    contender: PathBuf,
+
+    /// Engine whose results are compared.
+    #[arg(long, default_value = "turbopuffer")]
+    engine: String,
+
+    /// Minimum growth of the median latency, in percent, for a command to be flagged.
+    #[arg(long, default_value_t = 5.0)]
+    threshold: f64,
+
+    /// Maximum p-value for a change to be significant.
+    #[arg(long, default_value_t = 0.01)]
+    alpha: f64,
+
+    /// Also compare every query, printing those that regressed or got faster.
+    #[arg(long)]
+    per_query: bool,
+}
+
+#[derive(Deserialize)]
+struct Results {
+    /// Query results per command and engine.
+    results: BTreeMap<String, HashMap<String, Vec<QueryResult>>>,
+}
+
+#[derive(Deserialize)]
+struct QueryResult {
+    query: String,
+    count: i64,
+    /// Latencies in microseconds.
+    duration: Vec<f64>,
+    /// Number of iterations that timed out, which have no latency.
+    #[serde(default)]
+    timeouts: u64,
+}
+
+/// The change of the latency between two samples.
+struct Change {
+    baseline_median: f64,
+    contender_median: f64,
+    /// Change of the median, in percent.
+    percent: f64,
+    p: f64,
+}
+
+impl Change {
+    fn new(baseline: &mut [f64], contender: &mut [f64]) -> Change {
+        let p = mann_whitney_p(baseline, contender);
+        let baseline_median = median(baseline);
+        let contender_median = median(contender);
+        Change {
+            baseline_median,
+            contender_median,
+            percent: 100.0 * (contender_median - baseline_median) / baseline_median,
+            p,
+        }
+    }
+
+    fn verdict(&self, args: &Args) -> &'static str {
+        let significant = self.p < args.alpha;
+        if significant && self.percent > args.threshold {
+            "REGRESSION"
+        } else if significant && self.percent < -args.threshold {
+            "improvement"
+        } else {
+            ""
+        }
+    }
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    if !(args.threshold >= 0.0 && args.threshold.is_finite()) {
+        anyhow::bail!("--threshold must not be negative");
+    }
+    if !(args.alpha > 0.0 && args.alpha <= 1.0) {
+        anyhow::bail!("--alpha must be in (0, 1]");
+    }
+    let baseline_run = read_results(&args.baseline)?;
+    let contender_run = read_results(&args.contender)?;
+
+    let mut regressions = vec![];
+    println!(
+        "{:<24} {:>8} {:>14} {:>14} {:>9} {:>10}",
+        "command", "queries", "baseline p50", "contender p50", "change", "p-value"
+    );
+    for (command, engines) in &baseline_run.results {
+        let (Some(baseline), Some(contender)) = (
+            engines.get(&args.engine),
+            contender_run
+                .results
+                .get(command)
+                .and_then(|engines| engines.get(&args.engine)),
+        ) else {
+            continue;
+        };
+        let contender: HashMap<&str, &QueryResult> = contender
+            .iter()
+            .map(|result| (result.query.as_str(), result))
+            .collect();
+
+        let mut pooled_baseline = vec![];
+        let mut pooled_contender = vec![];
+        let mut queries = 0;
+        let mut count_mismatches = vec![];
+        let mut query_changes = vec![];
+        let mut timeouts = (0, 0);
+        for before in baseline {
+            let Some(after) = contender.get(before.query.as_str()) else {
+                continue;
+            };
+            timeouts.0 += before.timeouts;
+            timeouts.1 += after.timeouts;
+            // Unsupported queries have no latencies.
+            if before.duration.is_empty() || after.duration.is_empty() {
+                continue;
+            }
+            queries += 1;
+            if before.count != after.count {
+                count_mismatches.push((&before.query, before.count, after.count));
+            }
+            pooled_baseline.extend_from_slice(&before.duration);
+            pooled_contender.extend_from_slice(&after.duration);
+            if args.per_query {
+                let change = Change::new(&mut before.duration.clone(), &mut after.duration.clone());
+                if !change.verdict(&args).is_empty() {
+                    query_changes.push((&before.query, change));
+                }
+            }
+        }
+        if queries == 0 {
+            print_timeouts(command, timeouts);
+            continue;
+        }
+
+        let change = Change::new(&mut pooled_baseline, &mut pooled_contender);
+        let verdict = change.verdict(&args);
+        println!(
+            "{command:<24} {queries:>8} {:>12.0}µs {:>12.0}µs {:>+8.1}% {:>10.2e} {verdict}",
+            change.baseline_median, change.contender_median, change.percent, change.p,
+        );
+        if verdict == "REGRESSION" {
+            regressions.push(command.as_str());
+        }
+        query_changes.sort_by(|(_, a), (_, b)| b.percent.total_cmp(&a.percent));
+        for (query, change) in query_changes {
+            println!(
+                "  {:<22} {:>8} {:>12.0}µs {:>12.0}µs {:>+8.1}% {:>10.2e} {}",
+                truncate(query, 22),
+                "",
+                change.baseline_median,
+                change.contender_median,
+                change.percent,
+                change.p,
+                change.verdict(&args),
+            );
+        }
+        if !count_mismatches.is_empty() {
+            eprintln!(
+                "{command}: {} queries return a different count, e.g. {:?}: {} before, {} after",
+                count_mismatches.len(),
+                count_mismatches[0].0,
+                count_mismatches[0].1,
+                count_mismatches[0].2,
+            );
+        }
+        print_timeouts(command, timeouts);
+    }
+
+    if !regressions.is_empty() {
+        anyhow::bail!(
+            "{} commands regressed by more than {}%: {}",
+            regressions.len(),
+            args.threshold,
+            regressions.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Reports the iterations of `command` that timed out in the baseline and the contender.
+fn print_timeouts(command: &str, (baseline, contender): (u64, u64)) {
+    if baseline > 0 || contender > 0 {
+        eprintln!("{command}: {baseline} query iterations timed out before, {contender} after");
+    }
+}
+
+fn read_results(path: &Path) -> Result<Results, anyhow::Error> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    serde_json::from_reader(file)
+        .map_err(|error| anyhow::anyhow!("{}: invalid results file: {error}", path.display()))
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[middle]
+    } else {
+        (values[middle - 1] + values[middle]) / 2.0
+    }
+}
+
+/// The first `chars` characters of `query`, with an ellipsis if it is longer.
+fn truncate(query: &str, chars: usize) -> String {
+    if query.chars().count() <= chars {
+        return query.to_string();
+    }
+    let mut truncated: String = query.chars().take(chars - 1).collect();
+    truncated.push('…');
+    truncated
+}
@@ -1,157 +1,1872 @@
-use std::collections::HashMap;
-use std::io::BufRead;
-use std::sync::LazyLock;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use serde::Deserialize;
+use clap::Parser;
+use serde::de::DeserializeOwned;
+use tokio::task::{JoinError, JoinSet};
+use turbopuffer_bench::client::{
+    Access, AggregationResponse, ApiKeys, Client, ClientArgs, GroupedAggregationResponse,
+    MultiQueryResponse, QueryPerformance, QueryResponse, Response, Row, SubQueryResult,
+};
+use turbopuffer_bench::query::{
+    Consistency, CostClass, ParseOptions, Query, QueryVectors, load_cost_classes, merge_rankings,
+    reciprocal_rank_fusion,
+};
+use turbopuffer_bench::random::SplitMix64;
+use turbopuffer_bench::stats::LatencyHistogram;
+use turbopuffer_bench::{corpus, manifest, otlp};
 
-const API_URL: &str = "http://localhost:3001";
-const API_KEY: LazyLock<String> = LazyLock::new(|| {
-    std::env::var("TURBOPUFFER_API_KEY").expect("TURBOPUFFER_API_KEY must be set")
-});
+/// Reads `<COMMAND>\t<query>` lines from stdin and prints one result count per line.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    client: ClientArgs,
 
-const NAMESPACE: &str = "search-benchmark-game";
+    /// Use this top_k for every TOP_* command instead of the one in the command name.
+    #[arg(long)]
+    top_k_override: Option<usize>,
+
+    /// Maximum number of attempts per query. Queries failing with 429, 5xx or a connection error
+    /// are retried with exponential backoff.
+    #[arg(long, default_value_t = 5)]
+    max_attempts: u32,
+
+    /// Print the server-reported timings of every query to stderr.
+    #[arg(long)]
+    print_timings: bool,
+
+    /// Write the client-side latency histogram of all queries to this JSON file on exit.
+    #[arg(long)]
+    latency_histogram: Option<PathBuf>,
+
+    /// Run every query this many times, untimed and without output, before the measured pass.
+    /// This reads the whole query file from stdin before printing any result.
+    #[arg(long, default_value_t = 0)]
+    warmup_passes: usize,
+
+    /// Number of queries in flight. Values above 1 benchmark throughput rather than latency and
+    /// only make sense when stdin is a file rather than the interactive benchmark harness.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: u16,
+
+    /// Consistency level of the queries.
+    #[arg(long, value_enum, default_value = "eventual")]
+    consistency: Consistency,
+
+    /// Selectivities, in percent, of the `filter` tags the namespace was indexed with. Other
+    /// `*_FILTER_<pct>%` commands are reported as unsupported.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = corpus::FILTER_SELECTIVITIES,
+        value_parser = corpus::parse_selectivity
+    )]
+    filter_selectivities: Vec<f64>,
+
+    /// The namespace was built with `--synthetic-attributes`, supporting the commands that
+    /// filter, sort or group by them.
+    #[arg(long)]
+    synthetic_attributes: bool,
+
+    /// Abort unless the namespace holds this many documents, within
+    /// `--expected-docs-tolerance`. Without it, only empty namespaces are rejected.
+    #[arg(long, env = "TURBOPUFFER_EXPECTED_DOCS")]
+    expected_docs: Option<u64>,
+
+    /// Allowed relative difference between the namespace's approximate document count and
+    /// `--expected-docs`, in percent.
+    #[arg(long, default_value_t = 1.0)]
+    expected_docs_tolerance: f64,
+
+    /// What to do when a query has to search unindexed documents exhaustively, which means that
+    /// the index isn't up to date and latencies aren't representative.
+    #[arg(long, value_enum, default_value = "fail")]
+    on_exhaustive_search: ExhaustiveSearchPolicy,
+
+    /// Write one JSON record per query to this file, with the command, query, client-side
+    /// latency, result count and the performance stats reported by the server.
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Record the code, command line, query settings and timings of the run in the `do_query`
+    /// section of this JSON file, next to the `build_index` section of the index queried.
+    #[arg(long, default_value = "run-manifest.json")]
+    run_manifest: PathBuf,
+
+    /// Serve Prometheus metrics on this address, e.g. `127.0.0.1:9090`.
+    #[cfg(feature = "metrics")]
+    #[arg(long)]
+    metrics_addr: Option<std::net::SocketAddr>,
+
+    /// Query the namespaces `<namespace>-0` to `<namespace>-<N-1>` written by
+    /// `build_index --shards N`: every query is sent to all shards and their results are merged.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    shards: u16,
+
+    /// Print the ids of the returned documents after the count, tab-separated, in rank order.
+    #[arg(long)]
+    emit_ids: bool,
+
+    /// JSONL file with one `{"query": ..., "vector": [...]}` embedding per query, used by the
+    /// `ANN_*` and `HYBRID_*` commands. Without it, these commands are unsupported.
+    #[arg(long)]
+    query_vectors: Option<PathBuf>,
+
+    /// Send up to this many consecutive queries in one multi-query request. Every query of a
+    /// request is reported with the latency of the whole request; the latency amortized over the
+    /// queries of a request is reported on exit. Hybrid, paged and grouped queries are always sent
+    /// on their own.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    batch_size: u16,
+
+    /// Weight of the BM25 score of the `title` attribute in the ranking of `BOOSTED_*` commands.
+    #[arg(long, default_value_t = 2.0, value_parser = parse_boost)]
+    title_boost: f64,
+
+    /// Weight of the BM25 score of the `text` attribute in the ranking of `BOOSTED_*` commands.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_boost)]
+    text_boost: f64,
+
+    /// JSONL file written by `classify_queries` with the cost class of every query. The latency
+    /// percentiles of every class are reported on exit.
+    #[arg(long)]
+    query_classes: Option<PathBuf>,
+
+    /// Run the queries in a random order, the same for every `--seed`, rather than in the order of
+    /// the query file. Results are still printed in the order of the query file. This reads the
+    /// whole query file from stdin before printing any result.
+    #[arg(long)]
+    shuffle: bool,
+
+    /// Seed of the `--shuffle` permutation, the `--arrivals poisson` schedule and the
+    /// `--sample-responses` sample.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// How queries are sent. In a closed loop, a query is sent when one of the `--concurrency`
+    /// queries in flight completes, so a slow response delays the queries behind it without the
+    /// delay being measured (coordinated omission). In an open loop, queries are sent at `--rate`
+    /// whether or not earlier ones completed, and their latency is measured from the time they
+    /// were scheduled to be sent.
+    #[arg(long, value_enum, default_value = "closed")]
+    load: Load,
+
+    /// Queries per second sent with `--load open`.
+    #[arg(long, required_if_eq("load", "open"), value_parser = parse_rate)]
+    rate: Option<f64>,
+
+    /// Distribution of the time between two queries sent with `--load open`.
+    #[arg(long, value_enum, default_value = "poisson")]
+    arrivals: Arrivals,
+
+    /// Find the saturation point: loop over the query file in an open loop at every rate from
+    /// `<from>` to `<to>` queries per second in steps of `<step>`, e.g. `50:500:50`, for
+    /// `--ramp-step-duration` each, and print the latency and errors of every step. Stops at the
+    /// first saturated step: more than 1% errors, less than 90% of the target rate achieved, or
+    /// a p99 latency more than twice that of the first step. Nothing is printed per query.
+    #[arg(
+        long,
+        value_parser = parse_ramp,
+        conflicts_with_all = ["duration", "rate", "warmup_passes"]
+    )]
+    ramp: Option<Ramp>,
+
+    /// How long every rate of `--ramp` is held, e.g. `30s` or `2m`.
+    #[arg(long, default_value = "30s", value_parser = parse_duration)]
+    ramp_step_duration: Duration,
+
+    /// State of the server caches when queries run. Without it, queries run on whatever the
+    /// previous runs left in the caches.
+    #[arg(long, value_enum, conflicts_with = "ramp")]
+    cache_mode: Option<CacheMode>,
+
+    /// With `--cache-mode cold`, comma-separated namespaces holding the same documents, e.g.
+    /// built by `build_index --namespace`, queried in turn. A query finds the caches of its
+    /// namespace cold as long as they were evicted since the last query sent to it.
+    #[arg(long, value_delimiter = ',', required_if_eq("cache_mode", "cold"))]
+    cold_namespaces: Vec<String>,
+
+    /// Let the server idle this long, e.g. `10m`, after every `--idle-every` queries: the
+    /// queries read so far are sent and complete, then nothing is sent. The latency of the first
+    /// query after every idle period is reported on its own on exit, to capture how caches wake
+    /// up.
+    #[arg(long, value_parser = parse_duration, conflicts_with = "ramp")]
+    idle: Option<Duration>,
+
+    /// Number of queries between two `--idle` periods.
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    idle_every: u64,
+
+    /// Run every query this many times in a row and report the `--statistic` of its latencies,
+    /// like the other engines of the benchmark are measured.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    repeats: u16,
+
+    /// How the latencies of the `--repeats` runs of a query are combined.
+    #[arg(long, value_enum, default_value = "best")]
+    statistic: Statistic,
+
+    /// Loop over the query file until this much time has passed, e.g. `300s`, `5m` or `1h`,
+    /// instead of running it once. The latencies after the first pass are also reported on their
+    /// own, as the steady state. This reads the whole query file from stdin before printing any
+    /// result.
+    #[arg(long, value_parser = parse_duration)]
+    duration: Option<Duration>,
+
+    /// Abort queries taking longer than this, e.g. `5s`, retries included, print `TIMEOUT` as
+    /// their result and go on with the next ones. Timeouts are counted on exit. With `--repeats`,
+    /// the budget applies to every run of a query.
+    #[arg(long, value_parser = parse_duration)]
+    query_timeout: Option<Duration>,
+
+    /// Tab-separated file with one `<COMMAND>\t<query>\t<count>` line per query, e.g. the counts
+    /// of an exhaustive engine. The counts returned by the queries are compared with these, and
+    /// mismatches are reported on exit.
+    #[arg(long)]
+    expected_counts: Option<PathBuf>,
+
+    /// Report the distribution of the score of the last returned row of every command on exit,
+    /// the score a document needs to make the top k, to check that BM25 is configured like in
+    /// the other engines. Only rankings with a score, like BM25, are counted.
+    #[arg(long)]
+    score_stats: bool,
+
+    /// Save the full JSON responses of this percentage of the queries, sampled at random with
+    /// `--seed`, e.g. `1%`, to inspect their results or anomalous latencies afterwards.
+    #[arg(long, value_parser = parse_percentage)]
+    sample_responses: Option<f64>,
+
+    /// Directory of the `--sample-responses` files, one `<index>.json` per sampled query, where
+    /// `<index>` is the 0-based position of the query in the run.
+    #[arg(long, default_value = "responses")]
+    responses_dir: PathBuf,
+
+    /// Abort the run on the first unsupported command instead of printing `UNSUPPORTED` as its
+    /// result.
+    #[arg(long)]
+    strict: bool,
+
+    /// Only parse the query lines and build the request bodies, without sending any request, and
+    /// print the number of supported and unsupported queries of every command. Fails on
+    /// malformed lines, and with `--strict` on unsupported commands.
+    #[arg(long)]
+    dry_run: bool,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    let client = reqwest::Client::new();
-    let query_url = format!("{API_URL}/v2/namespaces/{NAMESPACE}/query");
-    let authorization_header = format!("Bearer {}", API_KEY.as_str());
+    let args = Args::parse();
+    #[cfg(feature = "metrics")]
+    if let Some(addr) = args.metrics_addr {
+        turbopuffer_bench::metrics::serve(addr).await?;
+    }
+    let options = ParseOptions {
+        top_k_override: args.top_k_override,
+        vectors: args
+            .query_vectors
+            .as_deref()
+            .map(QueryVectors::load)
+            .transpose()?,
+        consistency: args.consistency,
+        filter_selectivities: args.filter_selectivities.clone(),
+        synthetic_attributes: args.synthetic_attributes,
+        title_boost: args.title_boost,
+        text_boost: args.text_boost,
+    };
+    if args.dry_run {
+        return dry_run(&options, args.strict);
+    }
+    let api_keys = ApiKeys::from_env(Access::Read)?;
+    if (matches!(args.load, Load::Open) || args.ramp.is_some())
+        && (args.repeats > 1 || args.batch_size > 1)
+    {
+        anyhow::bail!("--load open and --ramp send every query once, on its own");
+    }
+    if args.idle.is_some() && (matches!(args.load, Load::Open) || args.repeats > 1) {
+        anyhow::bail!("--idle requires every query to be sent once, in a closed loop");
+    }
+    let client = Client::new(&args.client, &api_keys, args.max_attempts)?;
+    let clients: Arc<[Client]> = client.clone().shards(usize::from(args.shards)).into();
+    check_namespace(&clients, &args).await?;
+    let mut namespaces = Rotation {
+        copies: vec![clients.clone()],
+        next: 0,
+    };
+    let mut warmup_passes = args.warmup_passes;
+    match args.cache_mode {
+        Some(CacheMode::Cold) => {
+            if args.warmup_passes > 0 || args.repeats > 1 {
+                anyhow::bail!("--cache-mode cold runs every query once, without warmup");
+            }
+            // The turbopuffer API has no way to drop the caches of a namespace.
+            namespaces.copies.clear();
+            for namespace in &args.cold_namespaces {
+                let copy: Arc<[Client]> = client
+                    .with_namespace(namespace)
+                    .shards(usize::from(args.shards))
+                    .into();
+                check_namespace(&copy, &args).await?;
+                namespaces.copies.push(copy);
+            }
+        }
+        Some(CacheMode::Hot) if matches!(args.load, Load::Open) => {
+            anyhow::bail!("--cache-mode hot would delay the queries of the --load open schedule");
+        }
+        Some(CacheMode::Warm) => warmup_passes = warmup_passes.max(1),
+        Some(CacheMode::Hot) | None => {}
+    }
+    let prime = matches!(args.cache_mode, Some(CacheMode::Hot));
+    if let Some(ramp) = &args.ramp {
+        run_ramp(clients, &args, &options, ramp).await?;
+        otlp::flush().await;
+        return Ok(());
+    }
+    let mut stats = Stats {
+        output: match &args.output {
+            Some(path) => Some(std::io::BufWriter::new(std::fs::File::create(path)?)),
+            None => None,
+        },
+        classes: match &args.query_classes {
+            Some(path) => load_cost_classes(path)?,
+            None => HashMap::new(),
+        },
+        expected_counts: match &args.expected_counts {
+            Some(path) => load_expected_counts(path)?,
+            None => HashMap::new(),
+        },
+        ..Stats::default()
+    };
+
     let stdin = std::io::stdin();
-    for line in stdin.lock().lines() {
+    let mut lines: Box<dyn Iterator<Item = (usize, std::io::Result<String>)>> =
+        Box::new(stdin.lock().lines().enumerate());
+    if warmup_passes > 0 || args.shuffle || args.duration.is_some() {
+        // Warming up, shuffling and looping require the whole query file up front, so the queries
+        // can't be streamed.
+        let all_lines = lines.map(|(_, line)| line).collect::<Result<Vec<_>, _>>()?;
+        if warmup_passes > 0 {
+            let queries = all_lines
+                .iter()
+                .filter_map(|line| parse_line(line, &options))
+                .collect::<Vec<_>>();
+            let warmup_start = Instant::now();
+            for _ in 0..warmup_passes {
+                for query in &queries {
+                    within(args.query_timeout, execute(&clients, query)).await?;
+                }
+            }
+            eprintln!(
+                "warmup: {} passes over {} queries took {:?}",
+                warmup_passes,
+                queries.len(),
+                warmup_start.elapsed()
+            );
+        }
+        let mut indexed = all_lines.into_iter().enumerate().collect::<Vec<_>>();
+        if args.shuffle {
+            shuffle(&mut indexed, args.seed);
+        }
+        lines = match args.duration {
+            Some(duration) => {
+                let passes = indexed.len();
+                if passes == 0 {
+                    anyhow::bail!("--duration requires a non-empty query file");
+                }
+                // Every pass gets its own range of indices, so results are printed pass by pass.
+                stats.steady_state_from = Some(passes);
+                let deadline = Instant::now() + duration;
+                Box::new(
+                    indexed
+                        .into_iter()
+                        .cycle()
+                        .enumerate()
+                        .map(move |(i, (index, line))| (i / passes * passes + index, Ok(line)))
+                        .take_while(move |_| Instant::now() < deadline),
+                )
+            }
+            None => Box::new(indexed.into_iter().map(|(index, line)| (index, Ok(line)))),
+        };
+    }
+
+    if args.sample_responses.is_some() {
+        std::fs::create_dir_all(&args.responses_dir)?;
+    }
+    let mut sampler = args
+        .sample_responses
+        .map(|percent| (percent / 100.0, SplitMix64::new(args.seed)));
+    let started_at = manifest::unix_time();
+    let start = Instant::now();
+    let mut schedule = match (args.load, args.rate) {
+        (Load::Open, Some(rate)) => Some(Schedule::new(rate, args.arrivals, args.seed)),
+        _ => None,
+    };
+    let mut in_flight = JoinSet::new();
+    // Results are printed in input order, even if queries complete out of order.
+    let mut completed = BTreeMap::new();
+    let mut next_to_print = 0;
+    let mut batch = vec![];
+    let (repeats, statistic, timeout) = (args.repeats, args.statistic, args.query_timeout);
+    let run_batch = move |clients: Arc<[Client]>, batch, sampled| async move {
+        let run = execute_batch_repeated(&clients, batch, repeats, statistic, timeout, prime);
+        let (results, responses) = capture_responses(sampled, run).await;
+        Ok::<_, anyhow::Error>(with_responses(results?, responses))
+    };
+    // Set by an idle period until the next line that sends a query.
+    let mut idled = false;
+    for (position, (index, line)) in lines.enumerate() {
         let line = line?;
-        let fields: Vec<&str> = line.split("\t").collect();
-        assert_eq!(
-            fields.len(),
-            2,
-            "Expected a line in the format <COMMAND> query."
+        if let Some(idle) = args
+            .idle
+            .filter(|_| position > 0 && (position as u64).is_multiple_of(args.idle_every))
+        {
+            // The queries read before the idle period are sent before it.
+            if !batch.is_empty() {
+                let batch = mem::take(&mut batch);
+                in_flight.spawn(run_batch(namespaces.next(), batch, sample(&mut sampler)));
+            }
+            while let Some(joined) = in_flight.join_next().await {
+                for (index, query, result) in joined?? {
+                    completed.insert(index, Outcome::new(query, result));
+                }
+            }
+            tokio::time::sleep(idle).await;
+            idled = true;
+        }
+        let query = parse_line(&line, &options);
+        if query.is_some() && mem::take(&mut idled) {
+            stats.after_idle.insert(index);
+        }
+        match query {
+            Some(query) if args.batch_size > 1 && batchable(&query) => {
+                batch.push((index, query));
+                if batch.len() == usize::from(args.batch_size) {
+                    let batch = mem::take(&mut batch);
+                    in_flight.spawn(run_batch(namespaces.next(), batch, sample(&mut sampler)));
+                }
+            }
+            Some(query) => {
+                let scheduled = match &mut schedule {
+                    Some(schedule) => {
+                        let at = schedule.next();
+                        tokio::time::sleep_until(at.into()).await;
+                        Some(at)
+                    }
+                    None => None,
+                };
+                let clients = namespaces.next();
+                let (repeats, statistic) = (args.repeats, args.statistic);
+                let timeout = args.query_timeout;
+                let sampled = sample(&mut sampler);
+                in_flight.spawn(async move {
+                    let run =
+                        execute_repeated(&clients, &query, repeats, statistic, timeout, prime);
+                    let (result, responses) = capture_responses(sampled, run).await;
+                    let mut result = result?;
+                    if let Some(result) = &mut result {
+                        result.responses = responses;
+                        if let Some(scheduled) = scheduled {
+                            result.service_latency = Some(result.latency);
+                            result.latency = scheduled.elapsed();
+                        }
+                    }
+                    Ok(vec![(index, query, result)])
+                });
+            }
+            None => {
+                let command = line.split('\t').next().unwrap().to_string();
+                completed.insert(index, Outcome::Unsupported(command));
+            }
+        }
+        if schedule.is_some() {
+            // The open loop never waits for queries in flight, it only collects completed ones.
+            while let Some(joined) = in_flight.try_join_next() {
+                for (index, query, result) in joined?? {
+                    completed.insert(index, Outcome::new(query, result));
+                }
+            }
+        }
+        while schedule.is_none() && in_flight.len() >= usize::from(args.concurrency) {
+            for (index, query, result) in in_flight.join_next().await.unwrap()?? {
+                completed.insert(index, Outcome::new(query, result));
+            }
+        }
+        while let Some(outcome) = completed.remove(&next_to_print) {
+            stats.report(&args, next_to_print, outcome)?;
+            next_to_print += 1;
+        }
+    }
+    if !batch.is_empty() {
+        in_flight.spawn(run_batch(namespaces.next(), batch, sample(&mut sampler)));
+    }
+    while let Some(joined) = in_flight.join_next().await {
+        for (index, query, result) in joined?? {
+            completed.insert(index, Outcome::new(query, result));
+        }
+    }
+    for (index, outcome) in completed {
+        stats.report(&args, index, outcome)?;
+    }
+    let elapsed = start.elapsed();
+
+    eprintln!(
+        "{} queries needed more than one attempt",
+        stats.retried_queries
+    );
+    if stats.timed_out_queries > 0 {
+        eprintln!(
+            "{} queries timed out after {:?}",
+            stats.timed_out_queries,
+            args.query_timeout.unwrap_or_default()
+        );
+    }
+    if stats.exhaustive_queries > 0 {
+        eprintln!(
+            "{} queries searched unindexed documents exhaustively",
+            stats.exhaustive_queries
+        );
+    }
+    if stats.batched_queries > 0 {
+        eprintln!(
+            "amortized latency: {:.3}ms per query over {} queries sent in multi-query requests",
+            stats.amortized_latency.as_secs_f64() * 1000.0 / stats.batched_queries as f64,
+            stats.batched_queries
+        );
+    }
+    if !stats.latencies.is_empty() {
+        eprintln!("latency: {}", stats.latencies.summary());
+        let load = match (args.load, args.rate) {
+            (Load::Open, Some(rate)) => format!("open loop at {rate} queries/s"),
+            _ => format!("concurrency {}", args.concurrency),
+        };
+        eprintln!(
+            "throughput: {:.1} queries/s ({} queries in {elapsed:?}, {load})",
+            stats.latencies.len() as f64 / elapsed.as_secs_f64(),
+            stats.latencies.len(),
+        );
+    }
+    if !stats.service_latencies.is_empty() {
+        eprintln!(
+            "service time, from the actual send time: {}",
+            stats.service_latencies.summary()
+        );
+    }
+    if !stats.command_latencies.is_empty() {
+        print_command_table(&stats.command_latencies);
+    }
+    print_plan_table(&stats.plan_latencies);
+    if stats
+        .saturation
+        .values()
+        .any(|&(undersaturated, _)| undersaturated > 0)
+    {
+        eprintln!("queries returning fewer rows than their top_k, i.e. all their matches:");
+        for (command, (undersaturated, queries)) in &stats.saturation {
+            eprintln!("  {command}: {undersaturated} of {queries}");
+        }
+    }
+    if args.score_stats {
+        print_score_table(&mut stats.cutoff_scores);
+    }
+    if args.expected_counts.is_some() {
+        stats.print_count_mismatches();
+    }
+    if !stats.after_idle_latencies.is_empty() {
+        eprintln!(
+            "first query after {:?} idle: {}",
+            args.idle.unwrap_or_default(),
+            stats.after_idle_latencies.summary()
+        );
+    }
+    if !stats.steady_state_latencies.is_empty() {
+        eprintln!(
+            "steady-state latency, after the first pass: {}",
+            stats.steady_state_latencies.summary()
+        );
+    }
+    for (class, latencies) in &stats.class_latencies {
+        eprintln!(
+            "latency of {} queries: {}",
+            class.name(),
+            latencies.summary()
+        );
+    }
+    if !stats.server_latencies.is_empty() {
+        eprintln!(
+            "server processing: {} ({:.1}% of the client-side latency)",
+            stats.server_latencies.summary(),
+            stats.timed_server_total.as_secs_f64() * 100.0 / stats.timed_client_total.as_secs_f64()
         );
-        let command = fields[0];
-        let query = fields[1];
-        let (top_k, filter) = match command {
-            "TOP_10" => (10, None),
-            "TOP_100" => (100, None),
-            "TOP_1000" => (1000, None),
-            "TOP_10000" => (10000, None),
-            "TOP_10_FILTER_80%" => (10, Some("80%")),
-            "TOP_10_FILTER_20%" => (10, Some("20%")),
-            "TOP_10_FILTER_5%" => (10, Some("5%")),
-            "TOP_100_FILTER_80%" => (100, Some("80%")),
-            "TOP_100_FILTER_20%" => (100, Some("20%")),
-            "TOP_100_FILTER_5%" => (100, Some("5%")),
-            "TOP_1000_FILTER_80%" => (1000, Some("80%")),
-            "TOP_1000_FILTER_20%" => (1000, Some("20%")),
-            "TOP_1000_FILTER_5%" => (1000, Some("5%")),
-            "COUNT" => (0, None),
-            "COUNT_FILTER_80%" => (0, Some("80%")),
-            "COUNT_FILTER_20%" => (0, Some("20%")),
-            "COUNT_FILTER_5%" => (0, Some("5%")),
-            _ => {
-                println!("Unsupported command: {}", command);
-                continue;
+        eprintln!(
+            "network and client overhead: {}",
+            stats.network_latencies.summary()
+        );
+    }
+    if let Some(output) = &mut stats.output {
+        output.flush()?;
+    }
+    if let Some(path) = &args.latency_histogram {
+        stats.latencies.write_json(path)?;
+    }
+    manifest::record(
+        &args.run_manifest,
+        "do_query",
+        serde_json::json!({
+            "started_at": started_at,
+            "finished_at": manifest::unix_time(),
+            "namespace": args.client.namespace,
+            "shards": args.shards,
+            "queries": stats.latencies.len(),
+            "timed_out_queries": stats.timed_out_queries,
+            "concurrency": args.concurrency,
+            "rate": args.rate,
+            "batch_size": args.batch_size,
+            "repeats": args.repeats,
+            "warmup_passes": warmup_passes,
+            "top_k_override": args.top_k_override,
+            "consistency": args.consistency.level(),
+        }),
+    )?;
+    otlp::flush().await;
+    Ok(())
+}
+
+/// How the latencies of the runs of a query are combined into the reported one.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Statistic {
+    /// The fastest run.
+    Best,
+    /// The median run.
+    Median,
+    /// The mean of the runs.
+    Mean,
+}
+
+impl Statistic {
+    fn of(self, latencies: &mut [Duration]) -> Duration {
+        match self {
+            Statistic::Best => latencies.iter().copied().min().unwrap_or_default(),
+            Statistic::Median => {
+                latencies.sort();
+                latencies
+                    .get(latencies.len() / 2)
+                    .copied()
+                    .unwrap_or_default()
             }
+            Statistic::Mean => latencies.iter().sum::<Duration>() / latencies.len().max(1) as u32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum CacheMode {
+    /// Send the queries to the `--cold-namespaces` in turn.
+    Cold,
+    /// Run the query file once, untimed, before the measured pass: one `--warmup-passes`.
+    Warm,
+    /// Run every query once, untimed, right before its timed runs.
+    Hot,
+}
+
+/// The copies of the namespace queries are sent to in turn, a single one unless
+/// `--cache-mode cold`.
+struct Rotation {
+    copies: Vec<Arc<[Client]>>,
+    next: usize,
+}
+
+impl Rotation {
+    /// The clients of the shards of the next copy.
+    fn next(&mut self) -> Arc<[Client]> {
+        let copy = self.copies[self.next % self.copies.len()].clone();
+        self.next += 1;
+        copy
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Load {
+    /// Send a query when one of the queries in flight completes.
+    Closed,
+    /// Send queries at a fixed rate.
+    Open,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Arrivals {
+    /// Exponentially distributed gaps between queries, like independent users would send them.
+    Poisson,
+    /// The same gap between all queries.
+    Uniform,
+}
+
+/// The times at which the open loop sends queries.
+struct Schedule {
+    next: Instant,
+    rate: f64,
+    arrivals: Arrivals,
+    rng: SplitMix64,
+}
+
+impl Schedule {
+    fn new(rate: f64, arrivals: Arrivals, seed: u64) -> Schedule {
+        Schedule {
+            next: Instant::now(),
+            rate,
+            arrivals,
+            rng: SplitMix64::new(seed),
+        }
+    }
+
+    /// The time at which the next query is to be sent.
+    fn next(&mut self) -> Instant {
+        let at = self.next;
+        let gap = match self.arrivals {
+            Arrivals::Poisson => -(1.0 - self.rng.next_f64()).ln() / self.rate,
+            Arrivals::Uniform => 1.0 / self.rate,
         };
-        // Hack: detect if the query is an intersection query by checking for the presence of a "+"
-        // character. This works as long as queries don't mix required and optional terms.
-        let query_is_intersection = query.contains("+");
-        let mut filters = vec![];
-        if let Some(filter) = filter {
-            filters.push(["filter", "Contains", filter]);
-        }
-        if query_is_intersection {
-            filters.push(["text", "ContainsAllTokens", query]);
-        }
-        if top_k == 0 {
-            if !query_is_intersection {
-                filters.push(["text", "ContainsAnyToken", query]);
-            }
-            let body = match filters.as_slice() {
-                [filter] => serde_json::json!({
-                    "aggregate_by": {
-                        "count": ["Count"],
-                    },
-                    "filters": filter,
-                    "consistency": {"level": "eventual"},
-                }),
-                _ => serde_json::json!({
-                    "aggregate_by": {
-                        "count": ["Count"],
-                    },
-                    "filters": ["And", filters],
-                    "consistency": {"level": "eventual"},
-                }),
-            };
+        self.next += Duration::from_secs_f64(gap);
+        at
+    }
+}
 
-            let response = client
-                .post(&query_url)
-                .header("Authorization", &authorization_header)
-                .header("Content-Type", "application/json")
-                .json(&body)
-                .send()
-                .await?
-                .error_for_status()?
-                .json::<AggregationResponse>()
-                .await?;
+/// The target rates of `--ramp`, in queries per second.
+#[derive(Clone)]
+struct Ramp {
+    from: f64,
+    to: f64,
+    step: f64,
+}
+
+impl Ramp {
+    fn rates(&self) -> impl Iterator<Item = f64> {
+        let (from, step) = (self.from, self.step);
+        // Tolerates rounding errors, so that `0.1:0.3:0.1` ends at 0.3.
+        let steps = ((self.to - from) / step + 1e-9).floor() as u64;
+        (0..=steps).map(move |i| from + i as f64 * step)
+    }
+}
 
-            // Ensure the entire data set is indexed.
-            assert_eq!(response.performance.exhaustive_search_count, 0);
+/// The latencies and errors of a `--ramp` step.
+#[derive(Default)]
+struct StepStats {
+    latencies: LatencyHistogram,
+    errors: u64,
+    first_error: Option<String>,
+}
 
-            println!("{}", response.aggregations["count"]);
+impl StepStats {
+    fn record(&mut self, joined: Result<Result<Duration, anyhow::Error>, JoinError>) {
+        match joined
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result)
+        {
+            Ok(latency) => self.latencies.record(latency),
+            Err(err) => {
+                self.errors += 1;
+                self.first_error.get_or_insert_with(|| err.to_string());
+            }
+        }
+    }
+}
+
+/// Runs `--ramp` with the queries read from stdin, printing a line per step.
+async fn run_ramp(
+    clients: Arc<[Client]>,
+    args: &Args,
+    options: &ParseOptions,
+    ramp: &Ramp,
+) -> Result<(), anyhow::Error> {
+    let mut queries = vec![];
+    for line in std::io::stdin().lock().lines() {
+        if let Some(query) = parse_line(&line?, options) {
+            queries.push(Arc::new(query));
+        }
+    }
+    if queries.is_empty() {
+        anyhow::bail!("--ramp requires a query file with supported queries");
+    }
+    if args.shuffle {
+        shuffle(&mut queries, args.seed);
+    }
+
+    println!(
+        "{:>10} {:>12} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "target_qps", "achieved_qps", "errors", "p50_ms", "p90_ms", "p99_ms", "max_ms"
+    );
+    let ms = |latency: Duration| latency.as_secs_f64() * 1000.0;
+    let mut first_p99 = None;
+    let mut sustained = None;
+    let mut next_query = 0;
+    for rate in ramp.rates() {
+        let mut schedule = Schedule::new(rate, args.arrivals, args.seed);
+        let start = Instant::now();
+        let deadline = start + args.ramp_step_duration;
+        let mut step = StepStats::default();
+        let mut in_flight = JoinSet::new();
+        loop {
+            let at = schedule.next();
+            if at >= deadline {
+                break;
+            }
+            tokio::time::sleep_until(at.into()).await;
+            let query = queries[next_query % queries.len()].clone();
+            next_query += 1;
+            let clients = clients.clone();
+            let timeout = args.query_timeout;
+            in_flight.spawn(async move {
+                within(timeout, execute(&clients, &query))
+                    .await?
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("timed out after {:?}", timeout.unwrap_or_default())
+                    })?;
+                Ok(at.elapsed())
+            });
+            while let Some(joined) = in_flight.try_join_next() {
+                step.record(joined);
+            }
+        }
+        // The step ends once all its queries completed, so that steps don't overlap.
+        while let Some(joined) = in_flight.join_next().await {
+            step.record(joined);
+        }
+
+        let achieved = step.latencies.len() as f64 / start.elapsed().as_secs_f64();
+        let p99 = step.latencies.percentile(99.0);
+        println!(
+            "{rate:>10.1} {achieved:>12.1} {:>8} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+            step.errors,
+            ms(step.latencies.percentile(50.0)),
+            ms(step.latencies.percentile(90.0)),
+            ms(p99),
+            ms(step.latencies.max()),
+        );
+        if let Some(err) = &step.first_error {
+            eprintln!("first error at {rate} queries/s: {err}");
+        }
+        let sent = step.latencies.len() + step.errors;
+        let baseline_p99 = *first_p99.get_or_insert(p99);
+        let saturation = if step.errors * 100 > sent {
+            Some(format!("{} of {sent} queries failed", step.errors))
+        } else if achieved < 0.9 * rate {
+            Some(format!("only {achieved:.1} queries/s completed"))
+        } else if p99 > baseline_p99 * 2 {
+            Some(format!(
+                "p99 latency {p99:?}, more than twice the {baseline_p99:?} of the first step"
+            ))
         } else {
-            let body = match filters.as_slice() {
-                [] => serde_json::json!({
-                    "rank_by": [ "text", "BM25", query ],
-                    "top_k": top_k,
-                    "consistency": {"level": "eventual"},
-                }),
-                [filter] => serde_json::json!({
-                    "rank_by": [ "text", "BM25", query ],
-                    "filters": filter,
-                    "top_k": top_k,
-                    "consistency": {"level": "eventual"},
-                }),
-                _ => serde_json::json!({
-                    "rank_by": [ "text", "BM25", query ],
-                    "filters": ["And", filters],
-                    "top_k": top_k,
-                    "consistency": {"level": "eventual"},
-                }),
-            };
-            let response = client
-                .post(&query_url)
-                .header("Authorization", &authorization_header)
-                .header("Content-Type", "application/json")
-                .json(&body)
-                .send()
-                .await?
-                .error_for_status()?
-                .json::<QueryResponse>()
-                .await?;
+            None
+        };
+        if let Some(saturation) = saturation {
+            match sustained {
+                Some(sustained) => eprintln!(
+                    "saturated at {rate} queries/s ({saturation}); highest sustained rate: {sustained} queries/s"
+                ),
+                None => eprintln!("saturated at the first step, {rate} queries/s ({saturation})"),
+            }
+            return Ok(());
+        }
+        sustained = Some(rate);
+    }
+    eprintln!(
+        "no saturation up to {} queries/s",
+        sustained.unwrap_or(ramp.from)
+    );
+    Ok(())
+}
 
-            // Ensure the entire data set is indexed.
-            assert_eq!(response.performance.exhaustive_search_count, 0);
+/// Parses `<from>:<to>:<step>` rates in queries per second, for `--ramp`.
+fn parse_ramp(value: &str) -> Result<Ramp, anyhow::Error> {
+    let parts = value.split(':').collect::<Vec<_>>();
+    let [from, to, step] = parts[..] else {
+        anyhow::bail!("expected <from>:<to>:<step>, e.g. 50:500:50");
+    };
+    let ramp = Ramp {
+        from: parse_rate(from)?,
+        to: parse_rate(to)?,
+        step: parse_rate(step)?,
+    };
+    if ramp.to < ramp.from {
+        anyhow::bail!("the ramp must end above its start");
+    }
+    Ok(ramp)
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ExhaustiveSearchPolicy {
+    /// Abort the run.
+    Fail,
+    /// Print a warning for every such query.
+    Warn,
+    /// Only report the number of such queries at exit.
+    Count,
+}
 
-            println!("{}", response.rows.len());
+/// Checks that the namespaces exist and hold the expected number of documents, so that a missing
+/// or half-built index fails the run rather than producing misleading results.
+async fn check_namespace(clients: &[Client], args: &Args) -> Result<(), anyhow::Error> {
+    let mut docs = 0;
+    for client in clients {
+        let metadata = client.metadata().await.map_err(|err| {
+            anyhow::anyhow!(
+                "failed to read the metadata of namespace {}, was it built with build_index? {err}",
+                client.namespace()
+            )
+        })?;
+        docs += metadata.approx_row_count;
+    }
+    match args.expected_docs {
+        Some(expected) => {
+            let difference = docs.abs_diff(expected) as f64 * 100.0 / expected.max(1) as f64;
+            if difference > args.expected_docs_tolerance {
+                anyhow::bail!(
+                    "namespace {} holds about {docs} documents, expected {expected} (±{}%)",
+                    args.client.namespace,
+                    args.expected_docs_tolerance
+                );
+            }
         }
+        None if docs == 0 => anyhow::bail!("namespace {} is empty", args.client.namespace),
+        None => {}
     }
     Ok(())
 }
 
-#[derive(Deserialize)]
-struct QueryResponse {
-    rows: Vec<Row>,
-    performance: QueryPerformance,
+enum Outcome {
+    Unsupported(String),
+    TimedOut(Box<Query>),
+    Done(Box<(Query, QueryResult)>),
+}
+
+impl Outcome {
+    /// The outcome of `query`, without a result if it timed out.
+    fn new(query: Query, result: Option<QueryResult>) -> Outcome {
+        match result {
+            Some(result) => Outcome::Done(Box::new((query, result))),
+            None => Outcome::TimedOut(Box::new(query)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    retried_queries: usize,
+    timed_out_queries: usize,
+    exhaustive_queries: usize,
+    /// Number of queries sent in multi-query requests, and the sum of their request latencies
+    /// divided by the number of queries of the request.
+    batched_queries: usize,
+    amortized_latency: Duration,
+    latencies: LatencyHistogram,
+    /// With `--load open`, the latencies from the time queries were actually sent, which omit
+    /// the time they waited for the client.
+    service_latencies: LatencyHistogram,
+    /// The server processing time of the queries with one, and the rest of their client-side
+    /// latency: the network round trip and the client overhead.
+    server_latencies: LatencyHistogram,
+    network_latencies: LatencyHistogram,
+    /// Sums of the client-side latency and server processing time of the queries with one.
+    timed_client_total: Duration,
+    timed_server_total: Duration,
+    /// With `--duration`, the index of the first query of the second pass over the query file,
+    /// and the latencies from there on.
+    steady_state_from: Option<usize>,
+    steady_state_latencies: LatencyHistogram,
+    /// With `--idle`, the indices of the first queries after an idle period, and their latencies.
+    after_idle: HashSet<usize>,
+    after_idle_latencies: LatencyHistogram,
+    /// Latencies of the queries of every command.
+    command_latencies: BTreeMap<String, LatencyHistogram>,
+    /// Latencies of the queries of every command by [`QueryPerformance::plan`].
+    plan_latencies: BTreeMap<(String, String), LatencyHistogram>,
+    /// The number of top-k queries of every command returning fewer rows than top_k, and the
+    /// number of top-k queries.
+    saturation: BTreeMap<String, (usize, usize)>,
+    /// The [`QueryResult::cutoff_score`]s of every command.
+    cutoff_scores: BTreeMap<String, Vec<f64>>,
+    /// `--expected-counts` by command and query, the number of queries compared with them, and
+    /// the command, query, expected and returned count of those that differ.
+    expected_counts: HashMap<(String, String), u64>,
+    compared_counts: usize,
+    count_mismatches: Vec<(String, String, u64, u64)>,
+    /// Cost class of the queries, and the latencies of the queries of every class.
+    classes: HashMap<String, CostClass>,
+    class_latencies: BTreeMap<CostClass, LatencyHistogram>,
+    output: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl Stats {
+    /// Records a completed query and prints its result.
+    fn report(&mut self, args: &Args, index: usize, outcome: Outcome) -> Result<(), anyhow::Error> {
+        match outcome {
+            Outcome::Unsupported(command) => {
+                if args.strict {
+                    anyhow::bail!("unsupported command {command}");
+                }
+                // The placeholder the benchmark harness expects, keeping results aligned with
+                // the query lines.
+                println!("UNSUPPORTED");
+                eprintln!("unsupported command: {command}");
+                if let Some(output) = &mut self.output {
+                    let record = serde_json::json!({
+                        "command": command,
+                        "unsupported": true,
+                    });
+                    serde_json::to_writer(&mut *output, &record)?;
+                    writeln!(output)?;
+                }
+            }
+            Outcome::TimedOut(query) => {
+                self.timed_out_queries += 1;
+                println!("TIMEOUT");
+                eprintln!("timed out: {} {:?}", query.command, query.query);
+                if let Some(output) = &mut self.output {
+                    let record = serde_json::json!({
+                        "command": query.command,
+                        "query": query.query,
+                        "timed_out": true,
+                    });
+                    serde_json::to_writer(&mut *output, &record)?;
+                    writeln!(output)?;
+                }
+            }
+            Outcome::Done(done) => {
+                let (query, result) = *done;
+                let exhaustive = result.performance.exhaustive_search_count;
+                if exhaustive > 0 {
+                    self.exhaustive_queries += 1;
+                    let message = format!(
+                        "{} {:?} searched {exhaustive} unindexed documents exhaustively",
+                        query.command, query.query
+                    );
+                    match args.on_exhaustive_search {
+                        ExhaustiveSearchPolicy::Fail => {
+                            anyhow::bail!("{message}; is the index up to date?")
+                        }
+                        ExhaustiveSearchPolicy::Warn => eprintln!("warning: {message}"),
+                        ExhaustiveSearchPolicy::Count => {}
+                    }
+                }
+                if !result.responses.is_empty() {
+                    let record = serde_json::json!({
+                        "index": index,
+                        "command": query.command,
+                        "query": query.query,
+                        "latency_ms": result.latency.as_secs_f64() * 1000.0,
+                        "responses": result.responses,
+                    });
+                    let path = args.responses_dir.join(format!("{index}.json"));
+                    std::fs::write(path, serde_json::to_vec_pretty(&record)?)?;
+                }
+                if let Some(output) = &mut self.output {
+                    let mut record = serde_json::json!({
+                        "command": query.command,
+                        "query": query.query,
+                        "latency_ms": result.latency.as_secs_f64() * 1000.0,
+                        "count": result.count,
+                        "attempts": result.attempts,
+                        "batch_size": result.batch_size,
+                        "performance": result.performance,
+                        "plan": result.performance.plan(),
+                    });
+                    if !query.count_only {
+                        // A full page of rows may hide more matches, fewer rows are all of them.
+                        record["top_k"] = query.top_k.into();
+                        record["truncated"] = (result.count >= query.top_k as u64).into();
+                    }
+                    if let Some(score) = result.cutoff_score {
+                        record["cutoff_score"] = score.into();
+                    }
+                    if let Some(service_latency) = result.service_latency {
+                        record["service_latency_ms"] =
+                            (service_latency.as_secs_f64() * 1000.0).into();
+                    }
+                    if self.after_idle.contains(&index) {
+                        record["after_idle"] = true.into();
+                    }
+                    serde_json::to_writer(&mut *output, &record)?;
+                    writeln!(output)?;
+                }
+                self.latencies.record(result.latency);
+                if let Some(service_latency) = result.service_latency {
+                    self.service_latencies.record(service_latency);
+                }
+                if self.steady_state_from.is_some_and(|from| index >= from) {
+                    self.steady_state_latencies.record(result.latency);
+                }
+                if self.after_idle.contains(&index) {
+                    self.after_idle_latencies.record(result.latency);
+                }
+                self.command_latencies
+                    .entry(query.command.clone())
+                    .or_default()
+                    .record(result.latency);
+                self.plan_latencies
+                    .entry((query.command.clone(), result.performance.plan()))
+                    .or_default()
+                    .record(result.latency);
+                if !query.count_only {
+                    let (undersaturated, queries) =
+                        self.saturation.entry(query.command.clone()).or_default();
+                    *queries += 1;
+                    if result.count < query.top_k as u64 {
+                        *undersaturated += 1;
+                    }
+                }
+                let key = (query.command.clone(), query.query.clone());
+                if let Some(&expected) = self.expected_counts.get(&key) {
+                    self.compared_counts += 1;
+                    if expected != result.count {
+                        let (command, query) = key;
+                        self.count_mismatches
+                            .push((command, query, expected, result.count));
+                    }
+                }
+                if let Some(score) = result.cutoff_score {
+                    self.cutoff_scores
+                        .entry(query.command.clone())
+                        .or_default()
+                        .push(score);
+                }
+                if let Some(&class) = self.classes.get(&query.query) {
+                    self.class_latencies
+                        .entry(class)
+                        .or_default()
+                        .record(result.latency);
+                }
+                if let Some(server_ms) = result.performance.server_total_ms {
+                    let server = Duration::from_secs_f64(server_ms.max(0.0) / 1000.0);
+                    self.server_latencies.record(server);
+                    self.network_latencies
+                        .record(result.latency.saturating_sub(server));
+                    self.timed_client_total += result.latency;
+                    self.timed_server_total += server;
+                }
+                if result.batch_size > 1 {
+                    self.batched_queries += 1;
+                    self.amortized_latency += result.latency / result.batch_size as u32;
+                }
+                if result.attempts > 1 {
+                    self.retried_queries += 1;
+                }
+                if args.print_timings {
+                    print_timings(&query.command, &query.query, &result.performance);
+                }
+                if args.emit_ids && !query.count_only {
+                    println!("{}\t{}", result.count, result.ids.join("\t"));
+                } else {
+                    println!("{}", result.count);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints how many queries returned another count than `--expected-counts` to stderr, in
+    /// total, per command, and the first ones.
+    fn print_count_mismatches(&self) {
+        eprintln!(
+            "{} of {} queries compared with --expected-counts returned another count",
+            self.count_mismatches.len(),
+            self.compared_counts,
+        );
+        let mut per_command: BTreeMap<&str, usize> = BTreeMap::new();
+        for (command, ..) in &self.count_mismatches {
+            *per_command.entry(command).or_default() += 1;
+        }
+        for (command, mismatches) in per_command {
+            eprintln!("  {command}: {mismatches} mismatches");
+        }
+        for (command, query, expected, count) in self.count_mismatches.iter().take(10) {
+            eprintln!("  {command} {query:?}: expected {expected}, got {count}");
+        }
+    }
+}
+
+/// Loads `--expected-counts`, returning the count of every command and query.
+fn load_expected_counts(path: &Path) -> Result<HashMap<(String, String), u64>, anyhow::Error> {
+    let mut counts = HashMap::new();
+    for (index, line) in std::io::BufReader::new(std::fs::File::open(path)?)
+        .lines()
+        .enumerate()
+    {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let [command, query, count] = fields[..] else {
+            anyhow::bail!(
+                "{}:{}: expected <COMMAND>\\t<query>\\t<count>",
+                path.display(),
+                index + 1
+            );
+        };
+        let count = count.trim().parse().map_err(|err| {
+            anyhow::anyhow!("{}:{}: invalid count: {err}", path.display(), index + 1)
+        })?;
+        counts.insert((command.to_string(), query.to_string()), count);
+    }
+    Ok(counts)
 }
 
-#[derive(Deserialize)]
-struct AggregationResponse {
-    aggregations: HashMap<String, u64>,
+struct QueryResult {
+    /// The aggregated count for count queries, the number of returned rows otherwise.
+    count: u64,
+    /// Ids of the returned rows in rank order; empty for count queries.
+    ids: Vec<String>,
+    /// The score of the last returned row, for rankings with a score.
+    cutoff_score: Option<f64>,
+    /// With `--load open`, measured from the time the query was scheduled to be sent.
+    latency: Duration,
+    /// With `--load open`, the latency measured from the time the query was actually sent.
+    service_latency: Option<Duration>,
+    attempts: u32,
     performance: QueryPerformance,
+    /// Number of queries of the multi-query request that ran this query, including itself.
+    batch_size: usize,
+    /// With `--sample-responses`, the response bodies of the requests of a sampled query, of
+    /// all runs with `--repeats` and of all shards with `--shards`.
+    responses: Vec<serde_json::Value>,
+}
+
+/// Validates the query lines on stdin for `--dry-run`.
+fn dry_run(options: &ParseOptions, strict: bool) -> Result<(), anyhow::Error> {
+    // Number of supported and unsupported queries per command.
+    let mut commands: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    let mut malformed = 0;
+    for (index, line) in std::io::stdin().lock().lines().enumerate() {
+        let line = line?;
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let [command, query] = fields[..] else {
+            eprintln!(
+                "line {}: expected <COMMAND>\\t<query>, got {line:?}",
+                index + 1
+            );
+            malformed += 1;
+            continue;
+        };
+        let counts = commands.entry(command.to_string()).or_default();
+        match Query::parse(command, query, options) {
+            Some(_) => counts.0 += 1,
+            None => counts.1 += 1,
+        }
+    }
+    let width = commands.keys().map(String::len).max().unwrap_or(0).max(7);
+    println!(
+        "{:<width$} {:>10} {:>12}",
+        "command", "supported", "unsupported"
+    );
+    for (command, (supported, unsupported)) in &commands {
+        println!("{command:<width$} {supported:>10} {unsupported:>12}");
+    }
+    if malformed > 0 {
+        anyhow::bail!("{malformed} malformed lines");
+    }
+    let unsupported = commands
+        .values()
+        .map(|(_, unsupported)| unsupported)
+        .sum::<usize>();
+    if strict && unsupported > 0 {
+        anyhow::bail!("{unsupported} queries with an unsupported command");
+    }
+    Ok(())
+}
+
+/// Parses a `<COMMAND>\t<query>` line, returning `None` if the command isn't supported.
+fn parse_line(line: &str, options: &ParseOptions) -> Option<Query> {
+    let fields: Vec<&str> = line.split("\t").collect();
+    assert_eq!(
+        fields.len(),
+        2,
+        "Expected a line in the format <COMMAND> query."
+    );
+    Query::parse(fields[0], fields[1], options)
+}
+
+async fn execute(clients: &[Client], query: &Query) -> Result<QueryResult, anyhow::Error> {
+    let start = Instant::now();
+    let mut cutoff_score = None;
+    let (count, ids, attempts, performance) = if query.grouped {
+        let (responses, attempts, performance) =
+            scatter::<GroupedAggregationResponse>(clients, &query.body, |body| &body.performance)
+                .await?;
+        // Every document belongs to exactly one group.
+        let count = responses
+            .iter()
+            .flat_map(|body| &body.aggregation_groups)
+            .map(|group| group.count)
+            .sum();
+        (count, vec![], attempts, performance)
+    } else if query.count_only {
+        let (responses, attempts, performance) =
+            scatter::<AggregationResponse>(clients, &query.body, |body| &body.performance).await?;
+        let count = responses
+            .iter()
+            .map(|body| body.aggregations["count"])
+            .sum();
+        (count, vec![], attempts, performance)
+    } else if query.fused {
+        let (responses, attempts, performance) =
+            scatter::<MultiQueryResponse>(clients, &query.body, |body| &body.performance).await?;
+        let mut shard_rankings: Vec<Vec<Vec<Row>>> = query.orders.iter().map(|_| vec![]).collect();
+        for body in responses {
+            for (shards, result) in shard_rankings.iter_mut().zip(body.results) {
+                shards.push(result.rows);
+            }
+        }
+        let rankings = shard_rankings
+            .into_iter()
+            .zip(&query.orders)
+            .map(|(shards, &order)| merge_rankings(shards, query.top_k, order))
+            .collect::<Vec<_>>();
+        let ids = reciprocal_rank_fusion(&rankings, query.top_k);
+        let count = ids.len() as u64;
+        (count, ids, attempts, performance)
+    } else if let Some(page_size) = query.page_size {
+        let (ids, attempts, performance) = fetch_pages(clients, query, page_size).await?;
+        (ids.len() as u64, ids, attempts, performance)
+    } else {
+        let (responses, attempts, performance) =
+            scatter::<QueryResponse>(clients, &query.body, |body| &body.performance).await?;
+        let shards = responses.into_iter().map(|body| body.rows).collect();
+        let rows = merge_rankings(shards, query.top_k, query.orders[0]);
+        cutoff_score = rows.last().and_then(|row| row.dist);
+        let ids = rows.iter().map(Row::id_string).collect::<Vec<_>>();
+        let count = ids.len() as u64;
+        (count, ids, attempts, performance)
+    };
+    let latency = start.elapsed();
+
+    Ok(QueryResult {
+        count,
+        ids,
+        cutoff_score,
+        latency,
+        service_latency: None,
+        attempts,
+        performance,
+        batch_size: 1,
+        responses: vec![],
+    })
 }
 
-#[derive(Deserialize)]
-struct Row {}
+/// Runs `query` `repeats` times in a row and returns the result of the last run, with the
+/// `statistic` of the latencies of all runs and the highest number of attempts. `None` if a run
+/// took longer than `timeout`. With `prime`, the timed runs follow an untimed one.
+async fn execute_repeated(
+    clients: &[Client],
+    query: &Query,
+    repeats: u16,
+    statistic: Statistic,
+    timeout: Option<Duration>,
+    prime: bool,
+) -> Result<Option<QueryResult>, anyhow::Error> {
+    if prime && within(timeout, execute(clients, query)).await?.is_none() {
+        return Ok(None);
+    }
+    let mut latencies = vec![];
+    let mut attempts = 0;
+    for _ in 1..repeats {
+        let Some(result) = within(timeout, execute(clients, query)).await? else {
+            return Ok(None);
+        };
+        latencies.push(result.latency);
+        attempts = attempts.max(result.attempts);
+    }
+    let Some(mut result) = within(timeout, execute(clients, query)).await? else {
+        return Ok(None);
+    };
+    latencies.push(result.latency);
+    result.attempts = result.attempts.max(attempts);
+    result.latency = statistic.of(&mut latencies);
+    Ok(Some(result))
+}
+
+/// Like [`execute_repeated`], for the queries of a multi-query request.
+async fn execute_batch_repeated(
+    clients: &[Client],
+    batch: Vec<(usize, Query)>,
+    repeats: u16,
+    statistic: Statistic,
+    timeout: Option<Duration>,
+    prime: bool,
+) -> Result<Vec<(usize, Query, Option<QueryResult>)>, anyhow::Error> {
+    if prime
+        && within(timeout, execute_batch(clients, batch.clone()))
+            .await?
+            .is_none()
+    {
+        return Ok(timed_out(batch));
+    }
+    let mut latencies = vec![];
+    let mut attempts = 0;
+    for _ in 1..repeats {
+        let Some(results) = within(timeout, execute_batch(clients, batch.clone())).await? else {
+            return Ok(timed_out(batch));
+        };
+        latencies.push(results[0].2.latency);
+        attempts = attempts.max(results[0].2.attempts);
+    }
+    let Some(mut results) = within(timeout, execute_batch(clients, batch.clone())).await? else {
+        return Ok(timed_out(batch));
+    };
+    latencies.push(results[0].2.latency);
+    attempts = attempts.max(results[0].2.attempts);
+    let latency = statistic.of(&mut latencies);
+    for (_, _, result) in &mut results {
+        result.latency = latency;
+        result.attempts = attempts;
+    }
+    Ok(results
+        .into_iter()
+        .map(|(index, query, result)| (index, query, Some(result)))
+        .collect())
+}
+
+/// The results of the queries of `batch` when their request timed out.
+fn timed_out(batch: Vec<(usize, Query)>) -> Vec<(usize, Query, Option<QueryResult>)> {
+    batch
+        .into_iter()
+        .map(|(index, query)| (index, query, None))
+        .collect()
+}
 
-#[derive(Deserialize)]
-struct QueryPerformance {
-    exhaustive_search_count: u64,
+/// Gives every query of a multi-query request sampled by `--sample-responses` the responses
+/// of the request.
+fn with_responses(
+    mut results: Vec<(usize, Query, Option<QueryResult>)>,
+    responses: Vec<serde_json::Value>,
+) -> Vec<(usize, Query, Option<QueryResult>)> {
+    for result in results
+        .iter_mut()
+        .filter_map(|(_, _, result)| result.as_mut())
+    {
+        result.responses = responses.clone();
+    }
+    results
+}
+
+/// Whether the next query is sampled by `--sample-responses`.
+fn sample(sampler: &mut Option<(f64, SplitMix64)>) -> bool {
+    sampler
+        .as_mut()
+        .is_some_and(|(fraction, rng)| rng.next_f64() < *fraction)
+}
+
+/// Runs `run`, aborting it after `timeout` if any. `None` if it was aborted.
+async fn within<T>(
+    timeout: Option<Duration>,
+    run: impl Future<Output = Result<T, anyhow::Error>>,
+) -> Result<Option<T>, anyhow::Error> {
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, run).await {
+            Ok(result) => result.map(Some),
+            Err(_) => Ok(None),
+        },
+        None => run.await.map(Some),
+    }
+}
+
+/// Whether `query` can be a sub-query of a multi-query request: fused queries are multi-queries
+/// themselves, paged queries take several requests, and grouped counts have their own response.
+fn batchable(query: &Query) -> bool {
+    !query.fused && query.page_size.is_none() && !query.grouped
+}
+
+/// Runs `batch`, queries that are [`batchable`] with their input index, in one multi-query
+/// request. Every query gets the latency, attempts and performance of the whole request.
+async fn execute_batch(
+    clients: &[Client],
+    batch: Vec<(usize, Query)>,
+) -> Result<Vec<(usize, Query, QueryResult)>, anyhow::Error> {
+    let start = Instant::now();
+    let consistency = batch[0].1.body["consistency"].clone();
+    let queries = batch
+        .iter()
+        .map(|(_, query)| {
+            let mut body = query.body.clone();
+            // Consistency is set for the whole request.
+            body.as_object_mut().unwrap().remove("consistency");
+            body
+        })
+        .collect::<Vec<_>>();
+    let body = serde_json::json!({"queries": queries, "consistency": consistency});
+    let (responses, attempts, performance) =
+        scatter::<MultiQueryResponse>(clients, &body, |body| &body.performance).await?;
+    let latency = start.elapsed();
+    let mut shard_results: Vec<Vec<SubQueryResult>> = batch.iter().map(|_| vec![]).collect();
+    for body in responses {
+        for (shards, result) in shard_results.iter_mut().zip(body.results) {
+            shards.push(result);
+        }
+    }
+    let batch_size = batch.len();
+    Ok(batch
+        .into_iter()
+        .zip(shard_results)
+        .map(|((index, query), shards)| {
+            let (count, ids, cutoff_score) = if query.count_only {
+                let count = shards
+                    .iter()
+                    .map(|result| result.aggregations["count"])
+                    .sum();
+                (count, vec![], None)
+            } else {
+                let rankings = shards.into_iter().map(|result| result.rows).collect();
+                let rows = merge_rankings(rankings, query.top_k, query.orders[0]);
+                let ids = rows.iter().map(Row::id_string).collect::<Vec<_>>();
+                (ids.len() as u64, ids, rows.last().and_then(|row| row.dist))
+            };
+            let result = QueryResult {
+                count,
+                ids,
+                cutoff_score,
+                latency,
+                service_latency: None,
+                attempts,
+                performance: performance.clone(),
+                batch_size,
+                responses: vec![],
+            };
+            (index, query, result)
+        })
+        .collect())
+}
+
+/// Fetches the top_k rows of `query` page by page. With a [`Query::cursor`], every request asks
+/// for the rows after the last id of the previous page. Otherwise, e.g. for BM25 rankings, which
+/// have no cursor to resume from, every request asks for the rows up to the end of its page and
+/// skips the previous pages, like an offset would. Returns the ids, the highest number of
+/// attempts, and the performance of the last page with the server times and exhaustive search
+/// counts of all pages added up.
+async fn fetch_pages(
+    clients: &[Client],
+    query: &Query,
+    page_size: usize,
+) -> Result<(Vec<String>, u32, QueryPerformance), anyhow::Error> {
+    let mut body = query.body.clone();
+    let filters = query.body.get("filters").cloned();
+    let mut ids = vec![];
+    let mut attempts = 0;
+    let mut total: Option<QueryPerformance> = None;
+    while ids.len() < query.top_k {
+        let offset = if query.cursor { 0 } else { ids.len() };
+        body["top_k"] = (offset + page_size).into();
+        let (responses, page_attempts, performance) =
+            scatter::<QueryResponse>(clients, &body, |body| &body.performance).await?;
+        let shards = responses.into_iter().map(|body| body.rows).collect();
+        let rows = merge_rankings(shards, offset + page_size, query.orders[0]);
+        let page = rows
+            .iter()
+            .skip(offset)
+            .map(Row::id_string)
+            .collect::<Vec<_>>();
+        let last_page = page.len() < page_size;
+        if let Some(last) = rows.last().filter(|_| query.cursor) {
+            let after = serde_json::json!(["id", "Gt", last.id]);
+            body["filters"] = match &filters {
+                Some(filters) => serde_json::json!(["And", [filters, after]]),
+                None => after,
+            };
+        }
+        ids.extend(page);
+        attempts = attempts.max(page_attempts);
+        total = Some(match total {
+            None => performance,
+            Some(total) => QueryPerformance {
+                exhaustive_search_count: total.exhaustive_search_count
+                    + performance.exhaustive_search_count,
+                server_total_ms: add(total.server_total_ms, performance.server_total_ms),
+                query_execution_ms: add(total.query_execution_ms, performance.query_execution_ms),
+                ..performance
+            },
+        });
+        if last_page {
+            break;
+        }
+    }
+    Ok((
+        ids,
+        attempts,
+        total.expect("paged queries fetch at least one page"),
+    ))
+}
+
+fn add(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    Some(a? + b?)
+}
+
+/// Sends `body` to every shard concurrently. Returns the response bodies in shard order, the
+/// highest number of attempts, and the performance of the slowest shard with the exhaustive
+/// search counts of all shards added up.
+async fn scatter<T: DeserializeOwned + Send + 'static>(
+    clients: &[Client],
+    body: &serde_json::Value,
+    performance: impl Fn(&T) -> &QueryPerformance,
+) -> Result<(Vec<T>, u32, QueryPerformance), anyhow::Error> {
+    // Checked here rather than in the shard tasks, which don't inherit task-locals.
+    let capture = CAPTURED_RESPONSES.try_with(|_| ()).is_ok();
+    if let [client] = clients {
+        // Skip the task overhead in the common unsharded case.
+        let (response, raw) = query_raw::<T>(client, body, capture).await?;
+        keep_response(raw);
+        let performance = response_performance(&response, &performance);
+        return Ok((vec![response.body], response.attempts, performance));
+    }
+    let mut requests = JoinSet::new();
+    for (shard, client) in clients.iter().enumerate() {
+        let client = client.clone();
+        let body = body.clone();
+        requests.spawn(async move { (shard, query_raw::<T>(&client, &body, capture).await) });
+    }
+    let mut responses = Vec::with_capacity(clients.len());
+    while let Some(joined) = requests.join_next().await {
+        let (shard, response) = joined?;
+        responses.push((shard, response?));
+    }
+    responses.sort_by_key(|(shard, _)| *shard);
+    let responses = responses
+        .into_iter()
+        .map(|(shard, (response, raw))| {
+            keep_response(raw);
+            (shard, response)
+        })
+        .collect::<Vec<_>>();
+    let attempts = responses
+        .iter()
+        .map(|(_, response)| response.attempts)
+        .max()
+        .unwrap_or(1);
+    let performances = responses
+        .iter()
+        .map(|(_, response)| response_performance(response, &performance))
+        .collect::<Vec<_>>();
+    let bodies = responses
+        .into_iter()
+        .map(|(_, response)| response.body)
+        .collect::<Vec<_>>();
+    let mut slowest = performances[0].clone();
+    let mut exhaustive_search_count = 0;
+    for shard_performance in &performances {
+        exhaustive_search_count += shard_performance.exhaustive_search_count;
+        if shard_performance.server_total_ms > slowest.server_total_ms {
+            slowest = shard_performance.clone();
+        }
+    }
+    slowest.exhaustive_search_count = exhaustive_search_count;
+    Ok((bodies, attempts, slowest))
+}
+
+tokio::task_local! {
+    /// The raw bodies of the responses to the requests of a query sampled by
+    /// `--sample-responses`, set while it runs.
+    static CAPTURED_RESPONSES: RefCell<Vec<serde_json::Value>>;
+}
+
+/// Runs `run`, returning its output along with the raw bodies of the responses to its requests
+/// if `sampled`.
+async fn capture_responses<T>(
+    sampled: bool,
+    run: impl Future<Output = T>,
+) -> (T, Vec<serde_json::Value>) {
+    if !sampled {
+        return (run.await, vec![]);
+    }
+    CAPTURED_RESPONSES
+        .scope(RefCell::new(vec![]), async {
+            let output = run.await;
+            (output, CAPTURED_RESPONSES.with(RefCell::take))
+        })
+        .await
+}
+
+/// Sends `body` to `client`, also returning the raw response body if `capture`.
+async fn query_raw<T: DeserializeOwned>(
+    client: &Client,
+    body: &serde_json::Value,
+    capture: bool,
+) -> Result<(Response<T>, Option<serde_json::Value>), anyhow::Error> {
+    if !capture {
+        return Ok((client.query::<T>(body).await?, None));
+    }
+    let response = client.query::<serde_json::Value>(body).await?;
+    let typed = Response {
+        body: serde_json::from_value(response.body.clone())?,
+        attempts: response.attempts,
+        server_timing_ms: response.server_timing_ms,
+    };
+    Ok((typed, Some(response.body)))
+}
+
+/// Adds a raw response body returned by [`query_raw`] to those of the running query.
+fn keep_response(raw: Option<serde_json::Value>) {
+    if let Some(raw) = raw {
+        CAPTURED_RESPONSES.with(|responses| responses.borrow_mut().push(raw));
+    }
+}
+
+/// The performance reported in the body of `response`, with the server time of its
+/// `Server-Timing` header if the body has none.
+fn response_performance<T>(
+    response: &Response<T>,
+    performance: &impl Fn(&T) -> &QueryPerformance,
+) -> QueryPerformance {
+    let mut performance = performance(&response.body).clone();
+    performance.server_total_ms = performance.server_total_ms.or(response.server_timing_ms);
+    performance
+}
+
+/// Shuffles `items` with Fisher-Yates, driven by [`SplitMix64`] seeded with `seed` so that the
+/// permutation doesn't depend on the version of any crate.
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut rng = SplitMix64::new(seed);
+    for i in (1..items.len()).rev() {
+        let j = rng.below(i as u64 + 1) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// Parses a number of seconds, minutes or hours, e.g. `300s`, `5m` or `1h`. Plain numbers are
+/// seconds.
+fn parse_duration(value: &str) -> Result<Duration, anyhow::Error> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(unit_start) => value.split_at(unit_start),
+        None => (value, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => anyhow::bail!("unknown unit {unit:?}, expected s, m or h"),
+    };
+    let number: f64 = number.parse()?;
+    if !(number > 0.0 && number.is_finite()) {
+        anyhow::bail!("duration must be positive");
+    }
+    Ok(Duration::from_secs_f64(number * seconds))
+}
+
+/// Parses a percentage with an optional `%` sign, e.g. `1%` or `0.5`.
+fn parse_percentage(value: &str) -> Result<f64, anyhow::Error> {
+    let percent: f64 = value.strip_suffix('%').unwrap_or(value).parse()?;
+    if !(percent > 0.0 && percent <= 100.0) {
+        anyhow::bail!("percentage must be in (0, 100]");
+    }
+    Ok(percent)
+}
+
+fn parse_rate(value: &str) -> Result<f64, anyhow::Error> {
+    let rate: f64 = value.parse()?;
+    if !(rate > 0.0 && rate.is_finite()) {
+        anyhow::bail!("rate must be positive");
+    }
+    Ok(rate)
+}
+
+fn parse_boost(value: &str) -> Result<f64, anyhow::Error> {
+    let boost: f64 = value.parse()?;
+    if !(boost >= 0.0 && boost.is_finite()) {
+        anyhow::bail!("boost must be a non-negative number");
+    }
+    Ok(boost)
+}
+
+/// Prints the number of queries and the mean, p50 and p99 latency of every command to stderr.
+fn print_command_table(latencies: &BTreeMap<String, LatencyHistogram>) {
+    let width = latencies.keys().map(String::len).max().unwrap_or(0).max(7);
+    eprintln!(
+        "{:<width$} {:>8} {:>10} {:>10} {:>10}",
+        "command", "count", "mean_ms", "p50_ms", "p99_ms"
+    );
+    let ms = |latency: Duration| latency.as_secs_f64() * 1000.0;
+    for (command, latencies) in latencies {
+        eprintln!(
+            "{command:<width$} {:>8} {:>10.3} {:>10.3} {:>10.3}",
+            latencies.len(),
+            ms(latencies.mean()),
+            ms(latencies.percentile(50.0)),
+            ms(latencies.percentile(99.0)),
+        );
+    }
+}
+
+/// Prints the latencies of every plan of the commands whose queries were not all served the
+/// same way to stderr, since their latencies mix those of the plans.
+fn print_plan_table(latencies: &BTreeMap<(String, String), LatencyHistogram>) {
+    let mut plans = BTreeMap::<&str, usize>::new();
+    for (command, _) in latencies.keys() {
+        *plans.entry(command).or_default() += 1;
+    }
+    let mixed = latencies
+        .iter()
+        .filter(|((command, _), _)| plans[command.as_str()] > 1)
+        .collect::<Vec<_>>();
+    if mixed.is_empty() {
+        return;
+    }
+    let width = mixed
+        .iter()
+        .map(|((command, _), _)| command.len())
+        .max()
+        .unwrap_or(0)
+        .max(7);
+    eprintln!("commands whose queries were served with different plans:");
+    eprintln!(
+        "{:<width$} {:>8} {:>10} {:>10}  plan",
+        "command", "count", "p50_ms", "p99_ms"
+    );
+    let ms = |latency: Duration| latency.as_secs_f64() * 1000.0;
+    for ((command, plan), latencies) in mixed {
+        eprintln!(
+            "{command:<width$} {:>8} {:>10.3} {:>10.3}  {plan}",
+            latencies.len(),
+            ms(latencies.percentile(50.0)),
+            ms(latencies.percentile(99.0)),
+        );
+    }
+}
+
+/// Prints the number of scored queries and the min, median and max cutoff score of every command
+/// to stderr.
+fn print_score_table(scores: &mut BTreeMap<String, Vec<f64>>) {
+    if scores.is_empty() {
+        eprintln!("no query returned scored rows");
+        return;
+    }
+    let width = scores.keys().map(String::len).max().unwrap_or(0).max(7);
+    eprintln!(
+        "{:<width$} {:>8} {:>12} {:>12} {:>12}",
+        "command", "scored", "min_cutoff", "p50_cutoff", "max_cutoff"
+    );
+    for (command, scores) in scores {
+        scores.sort_by(f64::total_cmp);
+        eprintln!(
+            "{command:<width$} {:>8} {:>12.4} {:>12.4} {:>12.4}",
+            scores.len(),
+            scores[0],
+            scores[scores.len() / 2],
+            scores[scores.len() - 1],
+        );
+    }
+}
+
+fn print_timings(command: &str, query: &str, performance: &QueryPerformance) {
+    fn field<T: std::fmt::Display>(value: &Option<T>) -> String {
+        value
+            .as_ref()
+            .map_or_else(|| "-".to_string(), |value| value.to_string())
+    }
+    eprintln!(
+        "{command}\t{query}\tserver_total_ms={}\tquery_execution_ms={}\tcache_hit_ratio={}\tcache_temperature={}\tplan={}",
+        field(&performance.server_total_ms),
+        field(&performance.query_execution_ms),
+        field(&performance.cache_hit_ratio),
+        field(&performance.cache_temperature),
+        performance.plan(),
+    );
 }
@@ -0,0 +1,102 @@
+use std::io::Write;
+use std::time::Instant;
+
+use clap::Parser;
+use serde::Deserialize;
+use turbopuffer_bench::client::{Access, ApiKeys, Client, ClientArgs};
+use turbopuffer_bench::query::Consistency;
+
+/// Writes all documents of the namespace to stdout as JSONL, one object of attributes per line,
+/// to check a namespace built by `build_index` against its corpus, or to rebuild it, e.g. with
+/// another schema.
+///
+/// Documents are paged through in id order, each page resuming after the last id of the
+/// previous one, so that the export doesn't depend on a ranking being stable across pages.
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    client: ClientArgs,
+
+    /// Export the namespaces `<namespace>-0` to `<namespace>-<N-1>` written by
+    /// `build_index --shards N`, one after the other.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    shards: u16,
+
+    /// Number of documents per query.
+    #[arg(long, default_value_t = 1_000, value_parser = clap::value_parser!(u64).range(1..))]
+    page_size: u64,
+
+    /// Comma-separated attributes to export besides the id, e.g. `text` to compare with the
+    /// corpus. All attributes by default, including the synthetic ones added by `build_index`.
+    #[arg(long, value_delimiter = ',')]
+    attributes: Vec<String>,
+
+    /// Consistency level of the queries. Eventual consistency may miss recent writes.
+    #[arg(long, value_enum, default_value = "strong")]
+    consistency: Consistency,
+
+    /// Maximum number of attempts per query.
+    #[arg(long, default_value_t = 8)]
+    max_attempts: u32,
+}
+
+#[derive(Deserialize)]
+struct Page {
+    rows: Vec<serde_json::Map<String, serde_json::Value>>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let api_keys = ApiKeys::from_env(Access::Read)?;
+    let clients =
+        Client::new(&args.client, &api_keys, args.max_attempts)?.shards(usize::from(args.shards));
+    let include_attributes = if args.attributes.is_empty() {
+        serde_json::json!(true)
+    } else {
+        serde_json::json!(args.attributes)
+    };
+
+    let start = Instant::now();
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut total = 0;
+    for client in &clients {
+        let mut exported = 0;
+        let mut last_id = None;
+        loop {
+            let mut body = serde_json::json!({
+                "rank_by": ["id", "asc"],
+                "top_k": args.page_size,
+                "include_attributes": include_attributes,
+                "consistency": {"level": args.consistency.level()},
+            });
+            if let Some(last_id) = &last_id {
+                body["filters"] = serde_json::json!(["id", "Gt", last_id]);
+            }
+            let page = client.query::<Page>(&body).await?.body;
+            let Some(last) = page.rows.last() else {
+                break;
+            };
+            last_id = Some(last["id"].clone());
+            let full = page.rows.len() as u64 == args.page_size;
+            for mut row in page.rows {
+                row.remove("$dist");
+                serde_json::to_writer(&mut stdout, &row)?;
+                writeln!(stdout)?;
+                exported += 1;
+            }
+            if !full {
+                break;
+            }
+        }
+        eprintln!("{exported} documents exported from {}", client.namespace());
+        total += exported;
+    }
+    stdout.flush()?;
+    eprintln!(
+        "{total} documents exported in {:?} ({:.0} docs/s)",
+        start.elapsed(),
+        total as f64 / start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::io::Write;
+
+use clap::Parser;
+use turbopuffer_bench::corpus;
+use turbopuffer_bench::random::SplitMix64;
+
+/// Writes a synthetic corpus in the benchmark's JSONL format to stdout, to benchmark corpus
+/// sizes and term distributions that the Wikipedia corpus doesn't cover.
+///
+/// Words are drawn from a Zipfian distribution over a synthetic vocabulary, the most frequent
+/// words being the shortest like in natural language, and document lengths follow an
+/// exponential distribution. The output only depends on the options, including `--seed`.
+#[derive(Parser)]
+struct Args {
+    /// Number of documents to generate.
+    #[arg(long, default_value_t = 100_000)]
+    documents: u64,
+
+    /// Number of distinct words.
+    #[arg(long, default_value_t = 50_000)]
+    vocabulary: usize,
+
+    /// Exponent `s` of the Zipfian distribution: the word of rank `r` has a frequency
+    /// proportional to `1 / r^s`.
+    #[arg(long, default_value_t = 1.0)]
+    zipf_exponent: f64,
+
+    /// Mean number of words of a document.
+    #[arg(long, default_value_t = 200.0)]
+    mean_length: f64,
+
+    /// Minimum number of words of a document.
+    #[arg(long, default_value_t = 10)]
+    min_length: u64,
+
+    /// Maximum number of words of a document; longer documents are truncated.
+    #[arg(long, default_value_t = 5_000)]
+    max_length: u64,
+
+    /// Percentages of the documents to tag with `<pct>%`, written to the `filter` field so that
+    /// `build_index` keeps them.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = corpus::FILTER_SELECTIVITIES,
+        value_parser = corpus::parse_selectivity
+    )]
+    filter_selectivities: Vec<f64>,
+
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    if args.vocabulary == 0 {
+        anyhow::bail!("--vocabulary must be positive");
+    }
+    if !(args.zipf_exponent >= 0.0 && args.zipf_exponent.is_finite()) {
+        anyhow::bail!("--zipf-exponent must not be negative");
+    }
+    if args.min_length == 0 || args.min_length > args.max_length {
+        anyhow::bail!("--min-length must be positive and not exceed --max-length");
+    }
+    if !(args.mean_length >= args.min_length as f64 && args.mean_length.is_finite()) {
+        anyhow::bail!("--mean-length must be finite and at least --min-length");
+    }
+
+    let words: Vec<String> = (0..args.vocabulary).map(word).collect();
+    let mut cumulative = Vec::with_capacity(args.vocabulary);
+    let mut total = 0.0;
+    for rank in 1..=args.vocabulary {
+        total += (rank as f64).powf(-args.zipf_exponent);
+        cumulative.push(total);
+    }
+
+    let mut rng = SplitMix64::new(args.seed);
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut text = String::new();
+    let mut total_words = 0;
+    for i in 0..args.documents {
+        // Exponentially distributed above the minimum, so that the mean is `mean_length`.
+        let excess = -(1.0 - rng.next_f64()).ln() * (args.mean_length - args.min_length as f64);
+        let length = (args.min_length + excess.round() as u64).min(args.max_length);
+        total_words += length;
+
+        text.clear();
+        for _ in 0..length {
+            let x = rng.next_f64() * total;
+            let rank = cumulative
+                .partition_point(|&c| c <= x)
+                .min(args.vocabulary - 1);
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(&words[rank]);
+        }
+
+        let id = i.to_string();
+        let mut doc = serde_json::json!({ "id": id, "text": text });
+        let filter = corpus::filter_tags(&id, &args.filter_selectivities);
+        if !filter.is_empty() {
+            doc["filter"] = filter.into();
+        }
+        serde_json::to_writer(&mut stdout, &doc)?;
+        writeln!(stdout)?;
+    }
+    stdout.flush()?;
+    eprintln!(
+        "{} documents, {total_words} words, vocabulary of {} words",
+        args.documents, args.vocabulary,
+    );
+    Ok(())
+}
+
+/// The word of rank `rank`, 0-based: the rank written in bijective base 90 with consonant-vowel
+/// syllables as digits, so that words are distinct and frequent words are short.
+fn word(rank: usize) -> String {
+    const CONSONANTS: &[u8] = b"bcdfghjklmnprstvwz";
+    const VOWELS: &[u8] = b"aeiou";
+    let syllables = CONSONANTS.len() * VOWELS.len();
+    let mut word = String::new();
+    let mut n = rank + 1;
+    while n > 0 {
+        n -= 1;
+        let syllable = n % syllables;
+        word.push(CONSONANTS[syllable / VOWELS.len()] as char);
+        word.push(VOWELS[syllable % VOWELS.len()] as char);
+        n /= syllables;
+    }
+    word
+}
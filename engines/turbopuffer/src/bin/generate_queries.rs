@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+
+use clap::Parser;
+use turbopuffer_bench::corpus::{Document, terms};
+use turbopuffer_bench::random::SplitMix64;
+
+/// Counts the document frequencies of the terms of the corpus read from stdin, and writes
+/// queries of terms sampled from them to stdout as `<COMMAND>\t<query>` lines, the input of
+/// `do_query` and of the other engines, so that benchmarks don't need an external query file.
+///
+/// Terms are split by [`terms`] and sampled with a probability proportional to their document
+/// frequency raised to `--frequency-exponent`. The output only depends on the corpus and the
+/// options, including `--seed`.
+#[derive(Parser)]
+struct Args {
+    /// Number of queries to generate.
+    #[arg(long, default_value_t = 1_000)]
+    queries: usize,
+
+    /// Minimum number of distinct terms of a query.
+    #[arg(long, default_value_t = 1)]
+    min_terms: usize,
+
+    /// Maximum number of distinct terms of a query. The number of terms of every query is
+    /// uniformly distributed between the minimum and the maximum.
+    #[arg(long, default_value_t = 4)]
+    max_terms: usize,
+
+    /// Terms are sampled with a probability proportional to their document frequency raised to
+    /// this exponent: 1 samples terms like a reader of the corpus would, 0 samples them
+    /// uniformly from the vocabulary, which favors rare terms.
+    #[arg(long, default_value_t = 1.0)]
+    frequency_exponent: f64,
+
+    /// Terms occurring in fewer documents are never sampled, to leave out typos and ids.
+    #[arg(long, default_value_t = 2)]
+    min_document_frequency: u64,
+
+    /// Comma-separated commands with optional weights, e.g. `TOP_10=3,COUNT=1`. Every query is
+    /// assigned one of them with a probability proportional to its weight, 1 by default.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "TOP_10,TOP_100,COUNT",
+        value_parser = parse_command_weight
+    )]
+    commands: Vec<(String, f64)>,
+
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    if args.min_terms == 0 || args.min_terms > args.max_terms {
+        anyhow::bail!("--min-terms must be positive and not exceed --max-terms");
+    }
+    if !(args.frequency_exponent >= 0.0 && args.frequency_exponent.is_finite()) {
+        anyhow::bail!("--frequency-exponent must not be negative");
+    }
+    if !args.commands.iter().any(|(_, weight)| *weight > 0.0) {
+        anyhow::bail!("--commands needs a command with a positive weight");
+    }
+
+    let mut document_frequencies: HashMap<String, u64> = HashMap::new();
+    let mut documents = 0u64;
+    let mut seen = HashSet::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let doc: Document = serde_json::from_str(&line)?;
+        documents += 1;
+        seen.clear();
+        for term in terms(&doc.text) {
+            if !seen.contains(&term) {
+                *document_frequencies.entry(term.clone()).or_default() += 1;
+                seen.insert(term);
+            }
+        }
+    }
+
+    // Sorted so that the sampled queries don't depend on the iteration order of the hash map.
+    let mut vocabulary: Vec<(String, u64)> = document_frequencies
+        .into_iter()
+        .filter(|(_, frequency)| *frequency >= args.min_document_frequency)
+        .collect();
+    vocabulary.sort_unstable();
+    if vocabulary.len() < args.max_terms {
+        anyhow::bail!(
+            "the corpus on stdin has {} terms occurring in at least {} documents, fewer than \
+             --max-terms",
+            vocabulary.len(),
+            args.min_document_frequency,
+        );
+    }
+    let term_weights = cumulative(
+        vocabulary
+            .iter()
+            .map(|(_, frequency)| (*frequency as f64).powf(args.frequency_exponent)),
+    );
+    let command_weights = cumulative(args.commands.iter().map(|(_, weight)| *weight));
+
+    let mut rng = SplitMix64::new(args.seed);
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut query = vec![];
+    for _ in 0..args.queries {
+        let (command, _) = &args.commands[sample(&command_weights, &mut rng)];
+        let spread = (args.max_terms - args.min_terms + 1) as u64;
+        let length = args.min_terms + rng.below(spread) as usize;
+        query.clear();
+        while query.len() < length {
+            let term = vocabulary[sample(&term_weights, &mut rng)].0.as_str();
+            if !query.contains(&term) {
+                query.push(term);
+            }
+        }
+        writeln!(stdout, "{command}\t{}", query.join(" "))?;
+    }
+    stdout.flush()?;
+    eprintln!(
+        "{} queries from {} terms of {documents} documents",
+        args.queries,
+        vocabulary.len(),
+    );
+    Ok(())
+}
+
+/// The running sums of `weights`, for [`sample`].
+fn cumulative(weights: impl Iterator<Item = f64>) -> Vec<f64> {
+    let mut total = 0.0;
+    weights
+        .map(|weight| {
+            total += weight;
+            total
+        })
+        .collect()
+}
+
+/// An index drawn with a probability proportional to its weight, given the running sums of the
+/// weights.
+fn sample(cumulative: &[f64], rng: &mut SplitMix64) -> usize {
+    let x = rng.next_f64() * cumulative[cumulative.len() - 1];
+    cumulative
+        .partition_point(|&c| c <= x)
+        .min(cumulative.len() - 1)
+}
+
+/// Parses `<COMMAND>` or `<COMMAND>=<weight>`, for `--commands`.
+fn parse_command_weight(value: &str) -> Result<(String, f64), anyhow::Error> {
+    let (command, weight) = match value.split_once('=') {
+        Some((command, weight)) => (command, weight.parse::<f64>()?),
+        None => (value, 1.0),
+    };
+    if command.is_empty() || command.contains(char::is_whitespace) {
+        anyhow::bail!("invalid command {command:?}");
+    }
+    if !(weight >= 0.0 && weight.is_finite()) {
+        anyhow::bail!("the weight of a command must not be negative");
+    }
+    Ok((command.to_string(), weight))
+}
@@ -0,0 +1,773 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::otlp::{self, Span};
+use crate::retry::send_with_retry;
+
+/// Command line arguments selecting the turbopuffer deployment and namespace, shared by all
+/// binaries.
+#[derive(clap::Args)]
+pub struct ClientArgs {
+    /// Base URL of the turbopuffer API.
+    #[arg(
+        long,
+        env = "TURBOPUFFER_API_URL",
+        default_value = "http://localhost:3001",
+        value_parser = parse_api_url
+    )]
+    pub api_url: String,
+
+    /// turbopuffer namespace holding the benchmark corpus.
+    #[arg(
+        long,
+        env = "TURBOPUFFER_NAMESPACE",
+        default_value = "search-benchmark-game",
+        value_parser = parse_namespace
+    )]
+    pub namespace: String,
+
+    /// Export a span for every upsert and query request to this OTLP/HTTP collector, e.g.
+    /// `http://localhost:4318`.
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Speak HTTP/2 without negotiating it first, as plain `http://` URLs such as the local proxy
+    /// require. `https://` URLs negotiate HTTP/2 regardless.
+    #[arg(long)]
+    pub http2: bool,
+
+    /// Maximum number of idle connections kept open to the API. Unlimited by default.
+    #[arg(long)]
+    pub pool_max_idle: Option<usize>,
+
+    /// Seconds an idle connection is kept open before being closed.
+    #[arg(long, default_value_t = 90)]
+    pub pool_idle_timeout_secs: u64,
+
+    /// Send TCP keepalive probes on idle connections at this interval, in seconds.
+    #[arg(long)]
+    pub tcp_keepalive_secs: Option<u64>,
+
+    /// PEM file with the certificates of CAs to trust besides the system ones, for `https://`
+    /// deployments with a private CA.
+    #[arg(long, value_parser = read_ca_cert)]
+    pub ca_cert: Option<PemFile>,
+
+    /// Accept any server certificate, e.g. a self-signed one. The server isn't authenticated, so
+    /// this is only for test deployments.
+    #[arg(long)]
+    pub insecure_skip_verify: bool,
+
+    /// PEM file with the client certificate presented to deployments requiring mutual TLS.
+    #[arg(long, requires = "client_key", value_parser = read_pem)]
+    pub client_cert: Option<PemFile>,
+
+    /// PEM file with the PKCS#8 private key of `--client-cert`.
+    #[arg(long, requires = "client_cert", value_parser = read_pem)]
+    pub client_key: Option<PemFile>,
+
+    /// Extra HTTP header sent with every request, as `<name>:<value>`, e.g.
+    /// `X-Tpuf-Region:us-west` to benchmark another routing. Can be repeated.
+    #[arg(long = "header", value_parser = parse_header)]
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// What a binary does with the namespace, and so the API keys it needs.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Queries and metadata requests only.
+    Read,
+    /// Writes and deletes too.
+    ReadWrite,
+}
+
+/// The API keys of the requests. Benchmark environments may only give the query host a read-only
+/// key.
+pub struct ApiKeys {
+    /// Key of the queries and metadata requests.
+    pub query: Option<String>,
+    /// Key of the writes and deletes.
+    pub write: Option<String>,
+}
+
+impl ApiKeys {
+    /// Reads `TURBOPUFFER_QUERY_API_KEY` and `TURBOPUFFER_WRITE_API_KEY`, each falling back to
+    /// `TURBOPUFFER_API_KEY`. Called at startup, so that a missing or malformed key fails the run
+    /// before any work is done rather than at the first request.
+    pub fn from_env(access: Access) -> Result<ApiKeys, anyhow::Error> {
+        let shared = api_key_var("TURBOPUFFER_API_KEY")?;
+        let query = api_key_var("TURBOPUFFER_QUERY_API_KEY")?.or_else(|| shared.clone());
+        let write = api_key_var("TURBOPUFFER_WRITE_API_KEY")?.or(shared);
+        if query.is_none() {
+            anyhow::bail!(
+                "no API key: set TURBOPUFFER_API_KEY, or TURBOPUFFER_QUERY_API_KEY for the queries"
+            );
+        }
+        if access == Access::ReadWrite && write.is_none() {
+            anyhow::bail!(
+                "no API key for the writes: set TURBOPUFFER_API_KEY or TURBOPUFFER_WRITE_API_KEY"
+            );
+        }
+        Ok(ApiKeys { query, write })
+    }
+}
+
+/// The API key in the environment variable `name`, if it is set.
+fn api_key_var(name: &str) -> Result<Option<String>, anyhow::Error> {
+    let key = match std::env::var(name) {
+        Ok(key) => key,
+        Err(std::env::VarError::NotPresent) => return Ok(None),
+        Err(std::env::VarError::NotUnicode(_)) => anyhow::bail!("{name} is not valid UTF-8"),
+    };
+    if key.trim().is_empty() {
+        anyhow::bail!("{name} is set but empty: unset it or set it to an API key");
+    }
+    if HeaderValue::from_str(&format!("Bearer {key}")).is_err() {
+        anyhow::bail!(
+            "{name} has characters not allowed in an HTTP header, e.g. a trailing newline from \
+             the file it was read from"
+        );
+    }
+    Ok(Some(key))
+}
+
+/// The contents of a PEM file given on the command line.
+#[derive(Clone)]
+pub struct PemFile(Vec<u8>);
+
+/// A thin client for the turbopuffer HTTP API, bound to a single namespace.
+///
+/// Cloning is cheap and shares the underlying connection pool. Every request is retried on
+/// transient failures, see [`send_with_retry`].
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    api_url: String,
+    namespace: String,
+    /// `Authorization` headers of the queries and of the writes, if there is a key for them.
+    query_authorization: Option<String>,
+    write_authorization: Option<String>,
+    max_attempts: u32,
+    compression: Compression,
+}
+
+/// Content encoding of upsert request bodies.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// The `Content-Encoding` header of the compressed bodies.
+    fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gzip"),
+            Compression::Zstd => Some("zstd"),
+        }
+    }
+
+    fn compress(self, body: Vec<u8>) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(match self {
+            Compression::None => body,
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::with_capacity(body.len() / 4),
+                    flate2::Compression::default(),
+                );
+                encoder.write_all(&body)?;
+                encoder.finish()?
+            }
+            Compression::Zstd => {
+                zstd::encode_all(body.as_slice(), zstd::DEFAULT_COMPRESSION_LEVEL)?
+            }
+        })
+    }
+}
+
+/// A decoded response body along with the number of attempts it took to get it.
+pub struct Response<T> {
+    pub body: T,
+    pub attempts: u32,
+    /// The server processing time reported by the `Server-Timing` header, in milliseconds.
+    pub server_timing_ms: Option<f64>,
+}
+
+/// An upsert request with either `upsert_rows` or `upsert_columns`.
+#[derive(Serialize)]
+struct UpsertRequest<'a, R, C> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upsert_rows: Option<&'a [R]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upsert_columns: Option<&'a C>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema: Option<&'a serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance_metric: Option<&'a str>,
+    disable_backpressure: bool,
+}
+
+pub struct Upserted {
+    /// Number of rows upserted, as reported by the server.
+    pub rows_affected: u64,
+    /// Size of the JSON request body.
+    pub body_bytes: usize,
+    /// Size of the request body as sent, after compression.
+    pub sent_bytes: usize,
+    /// HTTP status of the response.
+    pub status: u16,
+}
+
+#[derive(Deserialize)]
+struct WriteResponse {
+    rows_affected: u64,
+}
+
+#[derive(Deserialize)]
+pub struct QueryResponse {
+    pub rows: Vec<Row>,
+    pub performance: QueryPerformance,
+}
+
+/// The response to a multi-query request, with one result per sub-query.
+#[derive(Deserialize)]
+pub struct MultiQueryResponse {
+    pub results: Vec<SubQueryResult>,
+    pub performance: QueryPerformance,
+}
+
+/// The result of a sub-query, with rows for top-k queries and aggregations for count queries.
+#[derive(Deserialize)]
+pub struct SubQueryResult {
+    #[serde(default)]
+    pub rows: Vec<Row>,
+    #[serde(default)]
+    pub aggregations: HashMap<String, u64>,
+}
+
+#[derive(Deserialize)]
+pub struct AggregationResponse {
+    pub aggregations: HashMap<String, u64>,
+    pub performance: QueryPerformance,
+}
+
+/// The response to a count query with a `group_by`, with one count per group.
+#[derive(Deserialize)]
+pub struct GroupedAggregationResponse {
+    pub aggregation_groups: Vec<AggregationGroup>,
+    pub performance: QueryPerformance,
+}
+
+#[derive(Deserialize)]
+pub struct AggregationGroup {
+    pub count: u64,
+}
+
+#[derive(Deserialize)]
+pub struct Row {
+    pub id: serde_json::Value,
+    /// The rank_by score, e.g. the BM25 score.
+    #[serde(rename = "$dist", default)]
+    pub dist: Option<f64>,
+    /// The `doc_length` attribute, only returned by queries sorting by it.
+    #[serde(default)]
+    pub doc_length: Option<u64>,
+}
+
+impl Row {
+    /// The document id, without quotes for string ids.
+    pub fn id_string(&self) -> String {
+        match &self.id {
+            serde_json::Value::String(id) => id.clone(),
+            id => id.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct QueryPerformance {
+    pub exhaustive_search_count: u64,
+    #[serde(default)]
+    pub approx_namespace_size: Option<u64>,
+    #[serde(default)]
+    pub cache_hit_ratio: Option<f64>,
+    #[serde(default)]
+    pub cache_temperature: Option<String>,
+    #[serde(default)]
+    pub server_total_ms: Option<f64>,
+    #[serde(default)]
+    pub query_execution_ms: Option<f64>,
+    /// The fields not listed above, e.g. how the server planned the query if it reports it.
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl QueryPerformance {
+    /// How the query was served, as far as the response tells: `exhaustive` if it scanned
+    /// unindexed documents and `indexed` otherwise, followed by the `key=value` of every string
+    /// or boolean field the server reports besides the known ones. Numbers vary from query to
+    /// query and are left out, so that queries served the same way have the same plan.
+    pub fn plan(&self) -> String {
+        let mut plan = if self.exhaustive_search_count > 0 {
+            "exhaustive".to_string()
+        } else {
+            "indexed".to_string()
+        };
+        for (key, value) in &self.other {
+            match value {
+                serde_json::Value::String(value) => plan.push_str(&format!(" {key}={value}")),
+                serde_json::Value::Bool(value) => plan.push_str(&format!(" {key}={value}")),
+                _ => {}
+            }
+        }
+        plan
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NamespaceMetadata {
+    /// Approximate number of documents in the namespace.
+    #[serde(default)]
+    pub approx_row_count: u64,
+    /// Approximate size of the documents in the namespace, in bytes.
+    #[serde(default)]
+    pub approx_logical_bytes: u64,
+    pub index: IndexStatus,
+}
+
+#[derive(Deserialize)]
+pub struct IndexStatus {
+    pub status: String,
+    pub unindexed_bytes: Option<usize>,
+}
+
+impl Client {
+    /// Creates a client, and starts the span exporter if `args` has an OTLP endpoint. Fails if
+    /// `--client-cert` and `--client-key` aren't a certificate and its PKCS#8 private key.
+    pub fn new(
+        args: &ClientArgs,
+        api_keys: &ApiKeys,
+        max_attempts: u32,
+    ) -> Result<Client, anyhow::Error> {
+        if let Some(endpoint) = &args.otlp_endpoint {
+            otlp::init(endpoint);
+        }
+        let mut http = reqwest::Client::builder()
+            .pool_idle_timeout(Duration::from_secs(args.pool_idle_timeout_secs))
+            .tcp_keepalive(args.tcp_keepalive_secs.map(Duration::from_secs));
+        if !args.headers.is_empty() {
+            http = http.default_headers(args.headers.iter().cloned().collect::<HeaderMap>());
+        }
+        if let Some(pool_max_idle) = args.pool_max_idle {
+            http = http.pool_max_idle_per_host(pool_max_idle);
+        }
+        if args.http2 {
+            http = http.http2_prior_knowledge();
+        }
+        if let Some(ca_cert) = &args.ca_cert {
+            for certificate in reqwest::Certificate::from_pem_bundle(&ca_cert.0)
+                .expect("CA certificates are validated when parsing the arguments")
+            {
+                http = http.add_root_certificate(certificate);
+            }
+        }
+        if args.insecure_skip_verify {
+            http = http.danger_accept_invalid_certs(true);
+        }
+        if let (Some(cert), Some(key)) = (&args.client_cert, &args.client_key) {
+            let identity = reqwest::Identity::from_pkcs8_pem(&cert.0, &key.0).map_err(|err| {
+                anyhow::anyhow!(
+                    "--client-cert and --client-key must hold a certificate and its PKCS#8 private \
+                     key, e.g. converted with `openssl pkcs8 -topk8 -nocrypt`: {err}"
+                )
+            })?;
+            http = http.identity(identity);
+        }
+        let http = http
+            .build()
+            .map_err(|err| anyhow::anyhow!("failed to initialize the HTTP client: {err}"))?;
+        Ok(Client {
+            http,
+            api_url: args.api_url.clone(),
+            namespace: args.namespace.clone(),
+            query_authorization: api_keys.query.as_ref().map(|key| format!("Bearer {key}")),
+            write_authorization: api_keys.write.as_ref().map(|key| format!("Bearer {key}")),
+            max_attempts,
+            compression: Compression::None,
+        })
+    }
+
+    /// Splits the client into one client per shard, bound to the namespaces `<namespace>-<i>`. A
+    /// single shard keeps the namespace as is.
+    pub fn shards(self, shards: usize) -> Vec<Client> {
+        if shards == 1 {
+            return vec![self];
+        }
+        (0..shards)
+            .map(|i| Client {
+                namespace: format!("{}-{i}", self.namespace),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// The same client, bound to `namespace` instead.
+    pub fn with_namespace(&self, namespace: &str) -> Client {
+        Client {
+            namespace: namespace.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// Compresses upsert requests with `compression`.
+    pub fn with_compression(mut self, compression: Compression) -> Client {
+        self.compression = compression;
+        self
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    fn query_authorization(&self) -> Result<&str, anyhow::Error> {
+        self.query_authorization.as_deref().ok_or_else(|| {
+            anyhow::anyhow!("TURBOPUFFER_QUERY_API_KEY or TURBOPUFFER_API_KEY must be set to query")
+        })
+    }
+
+    fn write_authorization(&self) -> Result<&str, anyhow::Error> {
+        self.write_authorization.as_deref().ok_or_else(|| {
+            anyhow::anyhow!("TURBOPUFFER_WRITE_API_KEY or TURBOPUFFER_API_KEY must be set to write")
+        })
+    }
+
+    pub async fn delete_namespace(&self) -> Result<(), anyhow::Error> {
+        let url = format!("{}/v1/namespaces/{}", self.api_url, self.namespace);
+        let authorization = self.write_authorization()?;
+        send_with_retry(self.max_attempts, || {
+            self.http
+                .delete(&url)
+                .header("Authorization", authorization)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Upserts `rows` with backpressure disabled, so that ingestion speed is not limited by
+    /// indexing speed.
+    ///
+    /// `schema` may be omitted once the namespace has one. `distance_metric` is required if the
+    /// rows have a `vector` attribute.
+    pub async fn upsert_rows<T: Serialize>(
+        &self,
+        rows: &[T],
+        schema: Option<&serde_json::Value>,
+        distance_metric: Option<&str>,
+    ) -> Result<Response<Upserted>, anyhow::Error> {
+        let request = UpsertRequest {
+            upsert_rows: Some(rows),
+            upsert_columns: None::<&()>,
+            schema,
+            distance_metric,
+            disable_backpressure: true,
+        };
+        self.upsert(serde_json::to_vec(&request)?, rows.len()).await
+    }
+
+    /// Like [`Client::upsert_rows`], with the `rows` rows given as one array of values per
+    /// attribute, all of the same length, instead of one object per row.
+    pub async fn upsert_columns<T: Serialize>(
+        &self,
+        columns: &T,
+        rows: usize,
+        schema: Option<&serde_json::Value>,
+        distance_metric: Option<&str>,
+    ) -> Result<Response<Upserted>, anyhow::Error> {
+        let request = UpsertRequest {
+            upsert_rows: None::<&[()]>,
+            upsert_columns: Some(columns),
+            schema,
+            distance_metric,
+            disable_backpressure: true,
+        };
+        self.upsert(serde_json::to_vec(&request)?, rows).await
+    }
+
+    /// Sends the serialized upsert request `json` of `rows` rows.
+    async fn upsert(
+        &self,
+        json: Vec<u8>,
+        rows: usize,
+    ) -> Result<Response<Upserted>, anyhow::Error> {
+        let url = format!("{}/v2/namespaces/{}", self.api_url, self.namespace);
+        let authorization = self.write_authorization()?;
+        let body_bytes = json.len();
+        let compression = self.compression;
+        let body = match compression {
+            Compression::None => json,
+            // Compressing a large batch takes a while, keep it off the async workers.
+            _ => tokio::task::spawn_blocking(move || compression.compress(json)).await??,
+        };
+        let mut span = Span::start("upsert");
+        span.set_str("namespace", &self.namespace);
+        span.set_int("batch_size", rows as i64);
+        span.set_int("body_bytes", body_bytes as i64);
+        let traceparent = span.traceparent();
+        let result = send_with_retry(self.max_attempts, || {
+            let mut request = self
+                .http
+                .post(&url)
+                .header("Authorization", authorization)
+                .header("Content-Type", "application/json");
+            if let Some(encoding) = compression.content_encoding() {
+                request = request.header("Content-Encoding", encoding);
+            }
+            if let Some(traceparent) = &traceparent {
+                request = request.header("traceparent", traceparent);
+            }
+            request.body(body.clone())
+        })
+        .await;
+        let (response, attempts) = end_span(span, result)?;
+        let server_timing_ms = server_timing_ms(response.headers());
+        let status = response.status().as_u16();
+        let response: WriteResponse = response.json().await?;
+        Ok(Response {
+            body: Upserted {
+                rows_affected: response.rows_affected,
+                body_bytes,
+                sent_bytes: body.len(),
+                status,
+            },
+            attempts,
+            server_timing_ms,
+        })
+    }
+
+    /// Deletes the documents with the given ids, returning the number of rows affected.
+    pub async fn delete_rows(&self, ids: &[&str]) -> Result<Response<u64>, anyhow::Error> {
+        self.write("delete", &serde_json::json!({"deletes": ids}))
+            .await
+    }
+
+    /// Deletes the documents matching `filter`, returning the number of rows affected.
+    pub async fn delete_by_filter(
+        &self,
+        filter: &serde_json::Value,
+    ) -> Result<Response<u64>, anyhow::Error> {
+        self.write(
+            "delete_by_filter",
+            &serde_json::json!({"delete_by_filter": filter}),
+        )
+        .await
+    }
+
+    /// Sends a write request without rows, traced as `name`.
+    async fn write(
+        &self,
+        name: &'static str,
+        body: &serde_json::Value,
+    ) -> Result<Response<u64>, anyhow::Error> {
+        let url = format!("{}/v2/namespaces/{}", self.api_url, self.namespace);
+        let authorization = self.write_authorization()?;
+        let mut span = Span::start(name);
+        span.set_str("namespace", &self.namespace);
+        let traceparent = span.traceparent();
+        let result = send_with_retry(self.max_attempts, || {
+            let mut request = self
+                .http
+                .post(&url)
+                .header("Authorization", authorization)
+                .header("Content-Type", "application/json");
+            if let Some(traceparent) = &traceparent {
+                request = request.header("traceparent", traceparent);
+            }
+            request.json(body)
+        })
+        .await;
+        let (response, attempts) = end_span(span, result)?;
+        let server_timing_ms = server_timing_ms(response.headers());
+        let response: WriteResponse = response.json().await?;
+        Ok(Response {
+            body: response.rows_affected,
+            attempts,
+            server_timing_ms,
+        })
+    }
+
+    pub async fn query<T: DeserializeOwned>(
+        &self,
+        body: &serde_json::Value,
+    ) -> Result<Response<T>, anyhow::Error> {
+        let url = format!("{}/v2/namespaces/{}/query", self.api_url, self.namespace);
+        let authorization = self.query_authorization()?;
+        let mut span = Span::start("query");
+        span.set_str("namespace", &self.namespace);
+        if let Some(top_k) = body.get("top_k").and_then(|top_k| top_k.as_i64()) {
+            span.set_int("top_k", top_k);
+        }
+        for key in ["rank_by", "filters", "aggregate_by"] {
+            if let Some(value) = body.get(key) {
+                span.set_str(key, &value.to_string());
+            }
+        }
+        let traceparent = span.traceparent();
+        let result = send_with_retry(self.max_attempts, || {
+            let mut request = self
+                .http
+                .post(&url)
+                .header("Authorization", authorization)
+                .header("Content-Type", "application/json");
+            if let Some(traceparent) = &traceparent {
+                request = request.header("traceparent", traceparent);
+            }
+            request.json(body)
+        })
+        .await;
+        let (response, attempts) = end_span(span, result)?;
+        let server_timing_ms = server_timing_ms(response.headers());
+        Ok(Response {
+            body: response.json().await?,
+            attempts,
+            server_timing_ms,
+        })
+    }
+
+    pub async fn metadata(&self) -> Result<NamespaceMetadata, anyhow::Error> {
+        let url = format!("{}/v1/namespaces/{}/metadata", self.api_url, self.namespace);
+        let authorization = self.query_authorization()?;
+        let (response, _) = send_with_retry(self.max_attempts, || {
+            self.http.get(&url).header("Authorization", authorization)
+        })
+        .await?;
+        Ok(response.json().await?)
+    }
+}
+
+/// Ends the span of a request with its outcome and number of attempts.
+fn end_span(
+    mut span: Span,
+    result: Result<(reqwest::Response, u32), anyhow::Error>,
+) -> Result<(reqwest::Response, u32), anyhow::Error> {
+    match &result {
+        Ok((response, attempts)) => {
+            span.set_int(
+                "http.response.status_code",
+                i64::from(response.status().as_u16()),
+            );
+            span.set_int("attempts", i64::from(*attempts));
+            span.end(None);
+        }
+        Err(err) => span.end(Some(err)),
+    }
+    result
+}
+
+/// The longest `dur` of the metrics of the `Server-Timing` headers, in milliseconds. Metrics may
+/// be nested, and the longest one covers the whole processing of the request.
+fn server_timing_ms(headers: &reqwest::header::HeaderMap) -> Option<f64> {
+    headers
+        .get_all("server-timing")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .flat_map(|metric| metric.split(';').skip(1))
+        .filter_map(|param| param.trim().strip_prefix("dur=")?.parse::<f64>().ok())
+        .reduce(f64::max)
+}
+
+fn parse_api_url(url: &str) -> Result<String, anyhow::Error> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|err| anyhow::anyhow!("invalid API URL {url:?}: {err}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        anyhow::bail!("invalid API URL {url:?}: scheme must be http or https");
+    }
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), anyhow::Error> {
+    let Some((name, value)) = header.split_once(':') else {
+        anyhow::bail!("invalid header {header:?}: expected <name>:<value>");
+    };
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|err| anyhow::anyhow!("invalid header name {name:?}: {err}"))?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|err| anyhow::anyhow!("invalid value of header {name}: {err}"))?;
+    Ok((name, value))
+}
+
+fn read_pem(path: &str) -> Result<PemFile, anyhow::Error> {
+    let pem = std::fs::read(path).map_err(|err| anyhow::anyhow!("failed to read {path}: {err}"))?;
+    Ok(PemFile(pem))
+}
+
+fn read_ca_cert(path: &str) -> Result<PemFile, anyhow::Error> {
+    let pem = read_pem(path)?;
+    let certificates = reqwest::Certificate::from_pem_bundle(&pem.0)
+        .map_err(|err| anyhow::anyhow!("invalid CA certificates in {path}: {err}"))?;
+    if certificates.is_empty() {
+        anyhow::bail!("{path} holds no certificate");
+    }
+    Ok(pem)
+}
+
+fn parse_namespace(namespace: &str) -> Result<String, anyhow::Error> {
+    let valid = !namespace.is_empty()
+        && namespace.len() <= 128
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        anyhow::bail!(
+            "invalid namespace {namespace:?}: must be 1-128 characters of [A-Za-z0-9-_.]"
+        );
+    }
+    Ok(namespace.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    /// Compresses `body` with `compression` and decodes it again.
+    fn round_trip(compression: Compression, body: &[u8]) -> Vec<u8> {
+        let compressed = compression.compress(body.to_vec()).unwrap();
+        match compression {
+            Compression::None => compressed,
+            Compression::Gzip => {
+                let mut decoded = Vec::new();
+                flate2::read::GzDecoder::new(compressed.as_slice())
+                    .read_to_end(&mut decoded)
+                    .unwrap();
+                decoded
+            }
+            Compression::Zstd => zstd::decode_all(compressed.as_slice()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn compression_round_trips() {
+        let rows = (0..1000)
+            .map(|id| format!(r#"{{"id":"{id}","text":"the quick brown fox {id}"}}"#))
+            .collect::<Vec<_>>()
+            .join(",");
+        let body = format!(r#"{{"upsert_rows":[{rows}]}}"#).into_bytes();
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            assert_eq!(round_trip(compression, b""), b"");
+            assert_eq!(round_trip(compression, &body), body);
+        }
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let compressed = compression.compress(body.clone()).unwrap();
+            assert!(compressed.len() < body.len() / 4);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! Corpus documents, and the synthetic attributes derived from the document id at index time on
+//! request, so that every corpus can be used with the attribute-based commands without
+//! preprocessing.
+
+use serde::{Deserialize, Serialize};
+
+/// A document of the JSONL corpus, upserted as one row.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Document {
+    /// Empty if the document has none, see `build_index --generate-ids`.
+    #[serde(default)]
+    pub id: String,
+    pub text: String,
+    /// The [`TITLE_ATTRIBUTE`], indexed for full-text search with `build_index --title-field`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Tags matched by the `*_FILTER_<pct>%` commands, replaced by [`augment`] with synthetic
+    /// ones on request.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filter: Vec<String>,
+    /// The [`TIMESTAMP_ATTRIBUTE`], set by [`augment`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// The [`DOC_LENGTH_ATTRIBUTE`], set by [`augment`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc_length: Option<u64>,
+    /// The [`GROUP_ATTRIBUTE`], set by [`augment`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<u64>,
+    /// The [`CODE_ATTRIBUTE`], set by [`augment`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector: Option<Vec<f32>>,
+    /// Any other attributes of the document, upserted as is.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Attribute holding the title of the document, ranked along with `text` by the `TITLE_BODY_*`
+/// commands.
+pub const TITLE_ATTRIBUTE: &str = "title";
+
+/// Attribute holding a synthetic timestamp, uniformly distributed in `0..TIMESTAMP_RANGE`.
+pub const TIMESTAMP_ATTRIBUTE: &str = "timestamp";
+pub const TIMESTAMP_RANGE: u64 = 1_000_000;
+
+/// Attribute holding the number of whitespace-separated words of the text.
+pub const DOC_LENGTH_ATTRIBUTE: &str = "doc_length";
+
+/// Attribute holding a synthetic group, uniformly distributed in `0..GROUPS`.
+pub const GROUP_ATTRIBUTE: &str = "group";
+pub const GROUPS: u64 = 16;
+
+/// Attribute holding a synthetic keyword of [`CODE_DIGITS`] uniformly distributed decimal digits,
+/// matched by the pattern filters of the `*_GLOB_<pct>%` and `*_REGEX_<pct>%` commands.
+pub const CODE_ATTRIBUTE: &str = "code";
+pub const CODE_DIGITS: u32 = 4;
+
+/// Default selectivities, in percent, of the synthetic `filter` tags, which are also those of the
+/// tags of the standard corpus.
+pub const FILTER_SELECTIVITIES: [f64; 3] = [80.0, 20.0, 5.0];
+
+const TIMESTAMP_SALT: u64 = 0x7469_6d65;
+const SHARD_SALT: u64 = 0x7368_6172;
+const FILTER_SALT: u64 = 0x6669_6c74;
+const GROUP_SALT: u64 = 0x6772_6f75;
+const SAMPLE_SALT: u64 = 0x7361_6d70;
+const CODE_SALT: u64 = 0x636f_6465;
+
+/// Adds the synthetic attributes to `doc` if `synthetic_attributes`. With `synthetic_filters`, its
+/// `filter` tags are replaced with a tag per selectivity, otherwise it keeps those of the corpus.
+/// Either every document of a corpus gets synthetic tags or none does: the standard corpus leaves
+/// `filter` off the documents without any tag, and tagging those would change the counts of the
+/// `*_FILTER_*` commands.
+pub fn augment(doc: &mut Document, synthetic_filters: Option<&[f64]>, synthetic_attributes: bool) {
+    if synthetic_attributes {
+        doc.timestamp = Some(timestamp(&doc.id));
+        doc.doc_length = Some(doc.text.split_whitespace().count() as u64);
+        doc.group = Some(derive(&doc.id, GROUP_SALT) % GROUPS);
+        doc.code = Some(format!(
+            "{:0width$}",
+            derive(&doc.id, CODE_SALT) % 10u64.pow(CODE_DIGITS),
+            width = CODE_DIGITS as usize
+        ));
+    }
+    if let Some(selectivities) = synthetic_filters {
+        doc.filter = filter_tags(&doc.id, selectivities);
+    }
+}
+
+/// Adds the synthetic attributes of [`augment`] to the namespace `schema`.
+pub fn add_synthetic_schema(schema: &mut serde_json::Value) {
+    for attribute in [TIMESTAMP_ATTRIBUTE, DOC_LENGTH_ATTRIBUTE, GROUP_ATTRIBUTE] {
+        schema[attribute] = serde_json::json!({
+            "type": "uint",
+            "filterable": true,
+        });
+    }
+    schema[CODE_ATTRIBUTE] = serde_json::json!({
+        "type": "string",
+        "filterable": true,
+        "regex": true,
+    });
+}
+
+pub fn timestamp(id: &str) -> u64 {
+    derive(id, TIMESTAMP_SALT) % TIMESTAMP_RANGE
+}
+
+/// The tags `<pct>%` of the document `id`, one per selectivity. Every tag is assigned to
+/// `<pct>` percent of the documents, independently of the other tags.
+pub fn filter_tags(id: &str, selectivities: &[f64]) -> Vec<String> {
+    selectivities
+        .iter()
+        .filter(|percent| unit(derive(id, FILTER_SALT ^ percent.to_bits())) < **percent / 100.0)
+        .map(|&percent| filter_tag(percent))
+        .collect()
+}
+
+/// The `filter` tag of the documents selected with `percent` percent probability.
+pub fn filter_tag(percent: f64) -> String {
+    format!("{percent}%")
+}
+
+/// Parses a selectivity in percent, for `--filter-selectivities`.
+pub fn parse_selectivity(value: &str) -> Result<f64, anyhow::Error> {
+    let percent: f64 = value.parse()?;
+    if !(percent > 0.0 && percent <= 100.0) {
+        anyhow::bail!("selectivity must be a percentage in (0, 100]");
+    }
+    Ok(percent)
+}
+
+/// Parses a fraction of the corpus in (0, 1], for `--fraction`.
+pub fn parse_fraction(value: &str) -> Result<f64, anyhow::Error> {
+    let fraction: f64 = value.parse()?;
+    if !(fraction > 0.0 && fraction <= 1.0) {
+        anyhow::bail!("fraction must be in (0, 1]");
+    }
+    Ok(fraction)
+}
+
+/// Whether the document `id` belongs to a sample of `fraction` of the corpus. The sample is the
+/// same across runs, and the sample of a smaller fraction is a subset of that of a larger one.
+pub fn sampled(id: &str, fraction: f64) -> bool {
+    unit(derive(id, SAMPLE_SALT)) < fraction
+}
+
+/// The shard, in `0..shards`, holding the document `id`.
+pub fn shard(id: &str, shards: usize) -> usize {
+    (derive(id, SHARD_SALT) % shards as u64) as usize
+}
+
+/// The `[start, end)` timestamp window, centered in the range, that matches `percent` percent of
+/// the documents.
+pub fn timestamp_window(percent: f64) -> (u64, u64) {
+    let width = (TIMESTAMP_RANGE as f64 * percent / 100.0).round() as u64;
+    let start = (TIMESTAMP_RANGE - width) / 2;
+    (start, start + width)
+}
+
+/// The leading digits of the codes matching `percent` percent of the documents: `0` for 10%,
+/// `00` for 1%, and so on. Other selectivities can't be matched by a prefix.
+pub fn code_prefix(percent: f64) -> Option<String> {
+    (1..=CODE_DIGITS)
+        .find(|&digits| percent == 100.0 / 10f64.powi(digits as i32))
+        .map(|digits| "0".repeat(digits as usize))
+}
+
+/// The lowercased runs of alphanumeric characters of `text`, which is close enough to the
+/// tokenizers of the engines to estimate term statistics.
+pub fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Maps a pseudo-random number uniformly to `[0, 1)`.
+fn unit(value: u64) -> f64 {
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// A pseudo-random number derived from `id` that is stable across runs, machines and Rust
+/// versions. Distinct salts give independent values for the same id.
+fn derive(id: &str, salt: u64) -> u64 {
+    // FNV-1a followed by the SplitMix64 finalizer, which FNV's weak low bits need.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in id.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    let mut z = hash ^ salt;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The share of 100,000 ids for which `selected` holds.
+    fn share(selected: impl Fn(&str) -> bool) -> f64 {
+        let ids = 100_000;
+        let selected = (0..ids).filter(|i| selected(&i.to_string())).count();
+        selected as f64 / ids as f64
+    }
+
+    #[test]
+    fn timestamp_window_selectivity() {
+        assert_eq!(timestamp_window(100.0), (0, TIMESTAMP_RANGE));
+        let (start, end) = timestamp_window(10.0);
+        assert_eq!(end - start, TIMESTAMP_RANGE / 10);
+        let share = share(|id| (start..end).contains(&timestamp(id)));
+        assert!((share - 0.1).abs() < 0.005, "{share}");
+    }
+
+    #[test]
+    fn filter_tag_selectivity() {
+        for percent in FILTER_SELECTIVITIES {
+            let tag = filter_tag(percent);
+            let share = share(|id| filter_tags(id, &FILTER_SELECTIVITIES).contains(&tag));
+            // Within 4 standard deviations of the binomial.
+            let p = percent / 100.0;
+            let tolerance = 4.0 * (p * (1.0 - p) / 100_000.0).sqrt();
+            assert!((share - p).abs() <= tolerance, "{tag}: {share}");
+        }
+        assert_eq!(filter_tags("id", &[100.0]), ["100%"]);
+    }
+
+    #[test]
+    fn filter_tag_names() {
+        assert_eq!(filter_tag(80.0), "80%");
+        assert_eq!(filter_tag(0.1), "0.1%");
+    }
+
+    #[test]
+    fn augment_keeps_corpus_tags() {
+        let mut doc = Document {
+            id: "7".to_string(),
+            text: "a b c".to_string(),
+            ..Document::default()
+        };
+        augment(&mut doc, None, false);
+        assert!(doc.filter.is_empty());
+        doc.filter = vec!["20%".to_string()];
+        augment(&mut doc, None, false);
+        assert_eq!(doc.filter, ["20%"]);
+        augment(&mut doc, Some(&[100.0]), false);
+        assert_eq!(doc.filter, ["100%"]);
+    }
+
+    #[test]
+    fn other_filter_selectivities() {
+        let selectivities = [50.0, 1.0, 0.1];
+        for percent in selectivities {
+            let tag = filter_tag(percent);
+            let share = share(|id| filter_tags(id, &selectivities).contains(&tag));
+            let p = percent / 100.0;
+            let tolerance = 4.0 * (p * (1.0 - p) / 100_000.0).sqrt();
+            assert!((share - p).abs() <= tolerance, "{tag}: {share}");
+        }
+    }
+
+    #[test]
+    fn filter_tags_are_independent() {
+        let all = share(|id| filter_tags(id, &FILTER_SELECTIVITIES).len() == 3);
+        // 80% * 20% * 5% = 0.8%.
+        assert!((all - 0.008).abs() < 0.002, "{all}");
+    }
+
+    #[test]
+    fn parse_selectivities() {
+        assert_eq!(parse_selectivity("0.1").unwrap(), 0.1);
+        assert_eq!(parse_selectivity("100").unwrap(), 100.0);
+        for invalid in ["0", "-5", "101", "NaN", "5%"] {
+            assert!(parse_selectivity(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn samples_are_nested() {
+        for i in 0..10_000 {
+            let id = i.to_string();
+            if sampled(&id, 0.1) {
+                assert!(sampled(&id, 0.5));
+            }
+        }
+        let share = share(|id| sampled(id, 0.1));
+        assert!((share - 0.1).abs() < 0.005, "{share}");
+        assert!((0..1000).all(|i| sampled(&i.to_string(), 1.0)));
+    }
+
+    #[test]
+    fn parse_fractions() {
+        assert_eq!(parse_fraction("0.01").unwrap(), 0.01);
+        assert_eq!(parse_fraction("1").unwrap(), 1.0);
+        for invalid in ["0", "-0.5", "1.5", "NaN"] {
+            assert!(parse_fraction(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn code_prefixes() {
+        assert_eq!(code_prefix(10.0).as_deref(), Some("0"));
+        assert_eq!(code_prefix(0.1).as_deref(), Some("000"));
+        assert_eq!(code_prefix(3.0), None);
+        assert_eq!(code_prefix(100.0), None);
+        let share = share(|id| {
+            let mut doc = Document {
+                id: id.to_string(),
+                ..Document::default()
+            };
+            augment(&mut doc, None, true);
+            doc.code.unwrap().starts_with("00")
+        });
+        assert!((share - 0.01).abs() < 0.002, "{share}");
+    }
+
+    #[test]
+    fn augment_adds_attributes_on_request() {
+        let mut doc = Document {
+            id: "7".to_string(),
+            text: "a b c".to_string(),
+            ..Document::default()
+        };
+        augment(&mut doc, None, false);
+        assert_eq!(doc.timestamp, None);
+        assert_eq!(doc.doc_length, None);
+        assert_eq!(doc.group, None);
+        assert_eq!(doc.code, None);
+        let row = serde_json::to_value(&doc).unwrap();
+        assert_eq!(row, serde_json::json!({"id": "7", "text": "a b c"}));
+        augment(&mut doc, None, true);
+        assert_eq!(doc.timestamp, Some(timestamp("7")));
+        assert_eq!(doc.doc_length, Some(3));
+        assert!(doc.group.is_some_and(|group| group < GROUPS));
+        assert!(doc.code.as_ref().is_some_and(|code| code.len() == 4));
+    }
+}
@@ -0,0 +1,13 @@
+pub mod client;
+pub mod corpus;
+pub mod manifest;
+pub mod metrics;
+pub mod otlp;
+#[cfg(feature = "parquet")]
+pub mod parquet_input;
+pub mod progress;
+pub mod query;
+pub mod random;
+pub mod rate_limit;
+mod retry;
+pub mod stats;
@@ -0,0 +1,63 @@
+//! The run manifest: what a benchmark run was made of, to reproduce or audit its results later.
+//!
+//! `build_index` and `do_query` each record their section of the same JSON file, so that the
+//! manifest next to a `results.json` describes both the index and the queries.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::SystemTime;
+
+/// Seconds since the Unix epoch.
+pub fn unix_time() -> f64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0.0, |time| time.as_secs_f64())
+}
+
+/// What every section records besides its own fields: the code and the command line that ran.
+pub fn environment() -> serde_json::Value {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    serde_json::json!({
+        "git_sha": git(&["rev-parse", "HEAD"]),
+        // Local changes make the commit alone insufficient to reproduce the run.
+        "git_dirty": git(&["status", "--porcelain"]).map(|status| !status.is_empty()),
+        "command_line": std::env::args().collect::<Vec<_>>(),
+        "version": env!("CARGO_PKG_VERSION"),
+    })
+}
+
+/// Sets the `section` of the manifest at `path` to `value` merged with the [`environment`],
+/// keeping the other sections.
+pub fn record(path: &Path, section: &str, value: serde_json::Value) -> Result<(), anyhow::Error> {
+    let mut manifest: serde_json::Value = match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|err| anyhow::anyhow!("invalid run manifest {}: {err}", path.display()))?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => serde_json::json!({}),
+        Err(err) => return Err(err.into()),
+    };
+    let mut entry = environment();
+    if let (Some(entry_fields), serde_json::Value::Object(fields)) = (entry.as_object_mut(), value)
+    {
+        entry_fields.extend(fields);
+    }
+    let Some(sections) = manifest.as_object_mut() else {
+        anyhow::bail!(
+            "invalid run manifest {}: expected a JSON object",
+            path.display()
+        );
+    };
+    sections.insert(section.to_string(), entry);
+    // Written to a temporary file renamed over the manifest, so that it is never truncated.
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(&manifest)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
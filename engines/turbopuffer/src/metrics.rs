@@ -0,0 +1,175 @@
+//! Process-wide request metrics, exposed in the Prometheus text format by [`serve`] when the
+//! `metrics` feature is enabled.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use crate::stats::LatencyHistogram;
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+#[derive(Default)]
+pub struct Metrics {
+    requests_in_flight: AtomicI64,
+    requests: AtomicU64,
+    request_errors: AtomicU64,
+    request_retries: AtomicU64,
+    /// The latencies are only recorded while [`serve`] runs, to keep a shared lock out of the
+    /// timed requests otherwise.
+    request_latency_us: AtomicU64,
+    request_latencies: Mutex<LatencyHistogram>,
+    serving: AtomicBool,
+    documents_indexed: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn request_started(&self) {
+        self.requests_in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records an attempt of a request, `failed` if it returned an error or an error status.
+    pub(crate) fn request_finished(&self, latency: Duration, failed: bool) {
+        self.requests_in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.request_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if cfg!(feature = "metrics") && self.serving.load(Ordering::Relaxed) {
+            self.request_latency_us
+                .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+            self.request_latencies.lock().unwrap().record(latency);
+        }
+    }
+
+    pub(crate) fn request_retried(&self) {
+        self.request_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn documents_indexed(&self, documents: u64) {
+        self.documents_indexed
+            .fetch_add(documents, Ordering::Relaxed);
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            let _ = write!(out, "{value}");
+        };
+        metric(
+            "turbopuffer_bench_requests_in_flight",
+            "gauge",
+            "Requests currently waiting for a response.",
+            format!(
+                "turbopuffer_bench_requests_in_flight {}\n",
+                self.requests_in_flight.load(Ordering::Relaxed)
+            ),
+        );
+        metric(
+            "turbopuffer_bench_requests_total",
+            "counter",
+            "Request attempts, retries included.",
+            format!(
+                "turbopuffer_bench_requests_total {}\n",
+                self.requests.load(Ordering::Relaxed)
+            ),
+        );
+        metric(
+            "turbopuffer_bench_request_errors_total",
+            "counter",
+            "Request attempts that failed with an error or an error status.",
+            format!(
+                "turbopuffer_bench_request_errors_total {}\n",
+                self.request_errors.load(Ordering::Relaxed)
+            ),
+        );
+        metric(
+            "turbopuffer_bench_request_retries_total",
+            "counter",
+            "Request attempts retried after a transient failure.",
+            format!(
+                "turbopuffer_bench_request_retries_total {}\n",
+                self.request_retries.load(Ordering::Relaxed)
+            ),
+        );
+        let latencies = self.request_latencies.lock().unwrap();
+        let mut summary = String::new();
+        for quantile in [0.5, 0.9, 0.99] {
+            let _ = writeln!(
+                summary,
+                "turbopuffer_bench_request_latency_seconds{{quantile=\"{quantile}\"}} {}",
+                latencies.percentile(quantile * 100.0).as_secs_f64()
+            );
+        }
+        let _ = writeln!(
+            summary,
+            "turbopuffer_bench_request_latency_seconds_sum {}",
+            self.request_latency_us.load(Ordering::Relaxed) as f64 / 1e6
+        );
+        let _ = writeln!(
+            summary,
+            "turbopuffer_bench_request_latency_seconds_count {}",
+            latencies.len()
+        );
+        drop(latencies);
+        metric(
+            "turbopuffer_bench_request_latency_seconds",
+            "summary",
+            "Latency of request attempts.",
+            summary,
+        );
+        metric(
+            "turbopuffer_bench_documents_indexed_total",
+            "counter",
+            "Documents acknowledged by upsert requests.",
+            format!(
+                "turbopuffer_bench_documents_indexed_total {}\n",
+                self.documents_indexed.load(Ordering::Relaxed)
+            ),
+        );
+        out
+    }
+}
+
+/// Serves [`METRICS`] on `GET /metrics` at `addr` until the process exits.
+#[cfg(feature = "metrics")]
+pub async fn serve(addr: std::net::SocketAddr) -> Result<(), anyhow::Error> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    METRICS.serving.store(true, Ordering::Relaxed);
+    eprintln!(
+        "serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                continue;
+            };
+            tokio::spawn(async move {
+                // Scrapes are tiny GET requests; the request line is all that matters.
+                let mut request = [0; 1024];
+                let Ok(len) = stream.read(&mut request).await else {
+                    return;
+                };
+                let response = if request[..len].starts_with(b"GET /metrics") {
+                    let body = METRICS.render();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    )
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    Ok(())
+}
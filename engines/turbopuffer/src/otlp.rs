@@ -0,0 +1,156 @@
+//! Optional export of request spans to an OpenTelemetry collector, over OTLP/HTTP with JSON
+//! encoding.
+//!
+//! Every upsert and query request becomes a root span, and its trace context is sent in a
+//! `traceparent` header so that server-side traces can be correlated with the client's view.
+
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+
+/// How often finished spans are sent to the collector.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+struct Exporter {
+    http: reqwest::Client,
+    url: String,
+    service_name: String,
+    spans: Mutex<Vec<serde_json::Value>>,
+}
+
+/// Starts exporting spans to the OTLP/HTTP collector at `endpoint`, e.g.
+/// `http://localhost:4318`. Only the first call has an effect.
+pub fn init(endpoint: &str) {
+    let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| {
+        std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.file_stem()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "turbopuffer-bench".to_string())
+    });
+    let exporter = Exporter {
+        http: reqwest::Client::new(),
+        url: format!("{}/v1/traces", endpoint.trim_end_matches('/')),
+        service_name,
+        spans: Mutex::new(vec![]),
+    };
+    if EXPORTER.set(exporter).is_ok() {
+        tokio::spawn(async {
+            loop {
+                tokio::time::sleep(EXPORT_INTERVAL).await;
+                flush().await;
+            }
+        });
+    }
+}
+
+/// Sends the finished spans to the collector. Call before exiting so that the last spans aren't
+/// lost. Export failures are reported on stderr and otherwise ignored.
+pub async fn flush() {
+    let Some(exporter) = EXPORTER.get() else {
+        return;
+    };
+    let spans = std::mem::take(&mut *exporter.spans.lock().unwrap());
+    if spans.is_empty() {
+        return;
+    }
+    let body = serde_json::json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute(
+                    "service.name",
+                    serde_json::json!({"stringValue": exporter.service_name}),
+                )],
+            },
+            "scopeSpans": [{
+                "scope": {"name": env!("CARGO_PKG_NAME")},
+                "spans": spans,
+            }],
+        }],
+    });
+    let result = exporter
+        .http
+        .post(&exporter.url)
+        .json(&body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(err) = result {
+        eprintln!("failed to export spans to {}: {err}", exporter.url);
+    }
+}
+
+/// A client span, recorded when ended if the exporter is running.
+pub struct Span {
+    name: &'static str,
+    trace_id: u128,
+    span_id: u64,
+    start: SystemTime,
+    attributes: Vec<serde_json::Value>,
+}
+
+impl Span {
+    pub fn start(name: &'static str) -> Span {
+        Span {
+            name,
+            trace_id: rand::random(),
+            span_id: rand::random(),
+            start: SystemTime::now(),
+            attributes: vec![],
+        }
+    }
+
+    /// The W3C trace context header value of the span, if spans are exported.
+    pub fn traceparent(&self) -> Option<String> {
+        EXPORTER.get()?;
+        Some(format!(
+            "00-{:032x}-{:016x}-01",
+            self.trace_id, self.span_id
+        ))
+    }
+
+    pub fn set_int(&mut self, key: &str, value: i64) {
+        let value = serde_json::json!({"intValue": value.to_string()});
+        self.attributes.push(attribute(key, value));
+    }
+
+    pub fn set_str(&mut self, key: &str, value: &str) {
+        let value = serde_json::json!({"stringValue": value});
+        self.attributes.push(attribute(key, value));
+    }
+
+    /// Ends the span, with an error status if `error` is set.
+    pub fn end(self, error: Option<&anyhow::Error>) {
+        let Some(exporter) = EXPORTER.get() else {
+            return;
+        };
+        let status = match error {
+            None => serde_json::json!({"code": 1}),
+            Some(err) => serde_json::json!({"code": 2, "message": err.to_string()}),
+        };
+        let span = serde_json::json!({
+            "traceId": format!("{:032x}", self.trace_id),
+            "spanId": format!("{:016x}", self.span_id),
+            "name": self.name,
+            // SPAN_KIND_CLIENT
+            "kind": 3,
+            "startTimeUnixNano": unix_nanos(self.start),
+            "endTimeUnixNano": unix_nanos(SystemTime::now()),
+            "attributes": self.attributes,
+            "status": status,
+        });
+        exporter.spans.lock().unwrap().push(span);
+    }
+}
+
+fn attribute(key: &str, value: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({"key": key, "value": value})
+}
+
+/// Nanoseconds since the Unix epoch, as a string as OTLP/JSON encodes 64-bit integers.
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
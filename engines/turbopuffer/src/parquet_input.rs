@@ -0,0 +1,126 @@
+//! Reads Parquet corpora as JSON lines, so that they go through the same parsing and batching as
+//! JSONL corpora.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ProjectionMask;
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+
+/// Rows read and converted at a time.
+const BATCH_ROWS: usize = 1024;
+
+/// The Parquet columns holding the attributes of the documents. Other columns aren't read.
+pub struct ColumnMapping {
+    pub id: String,
+    pub text: String,
+    pub filter: Option<String>,
+    /// Columns read under their own name, e.g. those of `--title-field` and `--vector-field`.
+    pub other: Vec<String>,
+}
+
+impl ColumnMapping {
+    /// The attribute and column names.
+    fn attributes(&self) -> Vec<(&str, &str)> {
+        let mut attributes = vec![("id", self.id.as_str()), ("text", self.text.as_str())];
+        if let Some(filter) = &self.filter {
+            attributes.push(("filter", filter.as_str()));
+        }
+        attributes.extend(
+            self.other
+                .iter()
+                .map(|column| (column.as_str(), column.as_str())),
+        );
+        attributes
+    }
+
+    /// Renames the columns of `batch` after the attributes. Ids are converted to strings, since
+    /// many datasets have integer ids.
+    fn apply(&self, batch: &RecordBatch) -> Result<RecordBatch, anyhow::Error> {
+        let mut fields = vec![];
+        let mut columns = vec![];
+        for (attribute, column) in self.attributes() {
+            let Some(array) = batch.column_by_name(column) else {
+                anyhow::bail!("Parquet column {column:?} not found");
+            };
+            let array = if attribute == "id" {
+                arrow_cast::cast(array, &DataType::Utf8)?
+            } else {
+                array.clone()
+            };
+            fields.push(Field::new(attribute, array.data_type().clone(), true));
+            columns.push(array);
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+}
+
+/// The rows of a Parquet file as JSON lines, one document per line. Null values are left out,
+/// so that e.g. documents without `filter` tags get the synthetic ones.
+pub struct ParquetLines {
+    batches: ParquetRecordBatchReader,
+    mapping: ColumnMapping,
+    /// The JSON lines of the current record batch, and how much of them has been read.
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl ParquetLines {
+    pub fn open(path: &Path, mapping: ColumnMapping) -> Result<ParquetLines, anyhow::Error> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+        for (_, column) in mapping.attributes() {
+            if builder.schema().field_with_name(column).is_err() {
+                anyhow::bail!("{} has no column {column:?}", path.display());
+            }
+        }
+        let projection = ProjectionMask::columns(
+            builder.parquet_schema(),
+            mapping.attributes().into_iter().map(|(_, column)| column),
+        );
+        let batches = builder
+            .with_projection(projection)
+            .with_batch_size(BATCH_ROWS)
+            .build()?;
+        Ok(ParquetLines {
+            batches,
+            mapping,
+            buffer: vec![],
+            position: 0,
+        })
+    }
+
+    /// Converts the next record batch into the buffer, returning `false` at the end of the file.
+    fn fill(&mut self) -> Result<bool, anyhow::Error> {
+        let Some(batch) = self.batches.next().transpose()? else {
+            return Ok(false);
+        };
+        let batch = self.mapping.apply(&batch)?;
+        self.buffer.clear();
+        self.position = 0;
+        let mut writer = arrow_json::LineDelimitedWriter::new(&mut self.buffer);
+        writer.write(&batch)?;
+        writer.finish()?;
+        Ok(true)
+    }
+}
+
+impl Read for ParquetLines {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.buffer.len() {
+            if !self.fill().map_err(std::io::Error::other)? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.buffer.len() - self.position);
+        buf[..n].copy_from_slice(&self.buffer[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
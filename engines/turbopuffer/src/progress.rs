@@ -0,0 +1,99 @@
+//! Periodic progress reports for long-running ingests.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Counters updated by the ingest loop and its upload tasks.
+pub struct Progress {
+    start: Instant,
+    /// Size of the input, if known, used to estimate the remaining time.
+    total_bytes: Option<u64>,
+    docs: AtomicU64,
+    bytes: AtomicU64,
+    acknowledged: AtomicU64,
+    in_flight: AtomicUsize,
+}
+
+impl Progress {
+    pub fn new(total_bytes: Option<u64>) -> Progress {
+        Progress {
+            start: Instant::now(),
+            total_bytes,
+            docs: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            acknowledged: AtomicU64::new(0),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Records a document of `bytes` bytes read from the input.
+    pub fn read(&self, bytes: usize) {
+        self.docs.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Records `docs` documents acknowledged by the server.
+    pub fn acknowledged(&self, docs: u64) {
+        self.acknowledged.fetch_add(docs, Ordering::Relaxed);
+    }
+
+    pub fn set_in_flight(&self, batches: usize) {
+        self.in_flight.store(batches, Ordering::Relaxed);
+    }
+
+    /// Prints a report every `interval` until the returned task is aborted. Rates are computed
+    /// over the last interval, the remaining time over the whole run.
+    pub fn spawn_reporter(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let progress = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            let mut last = (Instant::now(), 0, 0);
+            loop {
+                ticker.tick().await;
+                last = progress.report(last);
+            }
+        })
+    }
+
+    fn report(
+        &self,
+        (last_time, last_docs, last_bytes): (Instant, u64, u64),
+    ) -> (Instant, u64, u64) {
+        let now = Instant::now();
+        let docs = self.docs.load(Ordering::Relaxed);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let interval = now.duration_since(last_time).as_secs_f64();
+        let eta = match self.total_bytes {
+            Some(total_bytes) if bytes > 0 => {
+                let elapsed = now.duration_since(self.start).as_secs_f64();
+                let remaining = total_bytes.saturating_sub(bytes) as f64 * elapsed / bytes as f64;
+                format!(
+                    ", {:.1}% read, ETA {}",
+                    bytes as f64 * 100.0 / total_bytes as f64,
+                    format_duration(Duration::from_secs_f64(remaining))
+                )
+            }
+            _ => String::new(),
+        };
+        println!(
+            "progress: {docs} docs read ({:.0} docs/s, {:.1} MB/s), {} acknowledged, {} batches in flight{eta}",
+            (docs - last_docs) as f64 / interval,
+            (bytes - last_bytes) as f64 / 1e6 / interval,
+            self.acknowledged.load(Ordering::Relaxed),
+            self.in_flight.load(Ordering::Relaxed),
+        );
+        (now, docs, bytes)
+    }
+}
+
+/// Formats `duration` as e.g. `1h02m03s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m{s:02}s"),
+        (h, m, s) => format!("{h}h{m:02}m{s:02}s"),
+    }
+}
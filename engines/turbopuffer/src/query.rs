@@ -0,0 +1,1052 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::Row;
+use crate::corpus;
+
+/// A line of the query file translated into a turbopuffer query.
+#[derive(Clone)]
+pub struct Query {
+    pub command: String,
+    /// The query as written in the query file.
+    pub query: String,
+    /// Count queries aggregate the number of matches instead of returning rows.
+    pub count_only: bool,
+    /// Grouped count queries aggregate one count per value of [`corpus::GROUP_ATTRIBUTE`].
+    pub grouped: bool,
+    /// Hybrid queries run one sub-query per ranking and fuse the results client-side, see
+    /// [`reciprocal_rank_fusion`].
+    pub fused: bool,
+    pub top_k: usize,
+    /// Paged queries fetch their top_k rows in successive requests of this many rows. The body
+    /// requests the first page.
+    pub page_size: Option<usize>,
+    /// Whether paged queries ask for the rows after the last id of the previous page, rather than
+    /// for all rows up to the end of the page, skipping those of the previous pages.
+    pub cursor: bool,
+    /// How the rows of every ranking in the response are ordered: one entry per sub-query for
+    /// fused queries, none for count queries.
+    pub orders: Vec<Order>,
+    pub body: serde_json::Value,
+}
+
+/// Settings shared by all queries of a run.
+pub struct ParseOptions {
+    /// Replaces the top_k of every non-count command.
+    pub top_k_override: Option<usize>,
+    /// Embeddings of the queries, required by `ANN_*` commands.
+    pub vectors: Option<QueryVectors>,
+    pub consistency: Consistency,
+    /// Selectivities of the `filter` tags of the namespace; `*_FILTER_<pct>%` commands with other
+    /// selectivities aren't supported.
+    pub filter_selectivities: Vec<f64>,
+    /// Whether the documents of the namespace have the synthetic attributes of
+    /// [`corpus::augment`], required by the commands filtering, sorting or grouping by them.
+    pub synthetic_attributes: bool,
+    /// Weights of the BM25 scores of the title and the text in the ranking of `BOOSTED_*`
+    /// commands.
+    pub title_boost: f64,
+    pub text_boost: f64,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            top_k_override: None,
+            vectors: None,
+            consistency: Consistency::default(),
+            filter_selectivities: corpus::FILTER_SELECTIVITIES.to_vec(),
+            synthetic_attributes: false,
+            title_boost: 2.0,
+            text_boost: 1.0,
+        }
+    }
+}
+
+/// Consistency level of the queries.
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+pub enum Consistency {
+    /// Queries may not see the most recent writes, but skip a round trip to object storage.
+    #[default]
+    Eventual,
+    /// Queries see all acknowledged writes.
+    Strong,
+}
+
+impl Consistency {
+    /// The `level` of the `consistency` query parameter.
+    pub fn level(self) -> &'static str {
+        match self {
+            Consistency::Eventual => "eventual",
+            Consistency::Strong => "strong",
+        }
+    }
+}
+
+/// How expensive a query is expected to be, from the document frequencies of its terms, as
+/// assigned by `classify_queries`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CostClass {
+    Cheap,
+    Medium,
+    Expensive,
+}
+
+impl CostClass {
+    pub fn name(self) -> &'static str {
+        match self {
+            CostClass::Cheap => "cheap",
+            CostClass::Medium => "medium",
+            CostClass::Expensive => "expensive",
+        }
+    }
+}
+
+/// The record written by `classify_queries` for every query.
+#[derive(Serialize, Deserialize)]
+pub struct ClassifiedQuery {
+    pub query: String,
+    pub class: CostClass,
+    /// Sum of the document frequencies of the distinct terms of the query.
+    pub postings: u64,
+}
+
+/// Loads a JSONL file of [`ClassifiedQuery`] records, returning the class of every query.
+pub fn load_cost_classes(path: &Path) -> Result<HashMap<String, CostClass>, anyhow::Error> {
+    let mut classes = HashMap::new();
+    for line in std::io::BufReader::new(std::fs::File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: ClassifiedQuery = serde_json::from_str(&line)?;
+        classes.insert(record.query, record.class);
+    }
+    Ok(classes)
+}
+
+/// Precomputed query embeddings, keyed by query text.
+pub struct QueryVectors(HashMap<String, Vec<f32>>);
+
+#[derive(Deserialize)]
+struct QueryVectorRecord {
+    query: String,
+    vector: Vec<f32>,
+}
+
+impl ParseOptions {
+    fn vector(&self, query: &str) -> Option<&[f32]> {
+        self.vectors.as_ref()?.get(query)
+    }
+}
+
+impl QueryVectors {
+    /// Loads a JSONL file with one `{"query": ..., "vector": [...]}` record per query.
+    pub fn load(path: &Path) -> Result<QueryVectors, anyhow::Error> {
+        let mut vectors = HashMap::new();
+        for line in std::io::BufReader::new(std::fs::File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: QueryVectorRecord = serde_json::from_str(&line)?;
+            vectors.insert(record.query, record.vector);
+        }
+        Ok(QueryVectors(vectors))
+    }
+
+    fn get(&self, query: &str) -> Option<&[f32]> {
+        self.0.get(query).map(Vec::as_slice)
+    }
+}
+
+/// Loads a query file of `<COMMAND>\t<query>` lines for the benchmarks sending every query as a
+/// single request, skipping fused, paged and grouped queries and the commands `options` don't
+/// support. Returns the queries and the number of skipped lines, and fails if no query is left.
+pub fn load_single_request_queries(
+    path: &Path,
+    options: &ParseOptions,
+) -> Result<(Vec<Query>, usize), anyhow::Error> {
+    let mut queries = vec![];
+    let mut skipped = 0;
+    for line in std::fs::read_to_string(path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((command, query)) = line.split_once('\t') else {
+            anyhow::bail!("expected a line in the format <COMMAND>\\t<query>, got {line:?}");
+        };
+        match Query::parse(command, query, options) {
+            Some(query) if !query.fused && query.page_size.is_none() && !query.grouped => {
+                queries.push(query)
+            }
+            _ => skipped += 1,
+        }
+    }
+    if queries.is_empty() {
+        anyhow::bail!("no supported query in {}", path.display());
+    }
+    Ok((queries, skipped))
+}
+
+impl Query {
+    /// Translates `command` and `query`, returning `None` if the command isn't supported.
+    ///
+    /// `ANN_*` and `HYBRID_*` commands are only supported for queries with an embedding in
+    /// `options.vectors`, and the `*_RANGE_*`, `*_GLOB_*`, `*_REGEX_*`, `*_SORT_DOC_LENGTH_*` and
+    /// `*_GROUP_BY` commands with `options.synthetic_attributes`.
+    pub fn parse(command: &str, query: &str, options: &ParseOptions) -> Option<Query> {
+        let written = query;
+        let parsed = parse_command(command, &options.filter_selectivities)?;
+        if parsed.uses_synthetic_attributes() && !options.synthetic_attributes {
+            return None;
+        }
+        let Command {
+            top_k,
+            page_size,
+            cursor,
+            filter,
+            terms,
+            sort,
+            group_by,
+            snippets,
+        } = parsed;
+        let top_k = match options.top_k_override {
+            Some(top_k_override) if top_k > 0 && page_size.is_none() => top_k_override,
+            _ => top_k,
+        };
+        // Hack: detect if the query is an intersection query by checking for the presence of a "+"
+        // character. This works as long as queries don't mix required and optional terms.
+        let query_is_intersection = query.contains("+");
+        let mut filters = vec![];
+        if let Some(filter) = &filter {
+            filters.push(filter.to_json());
+        }
+        let typed;
+        // Phrase queries are quoted in the query file.
+        let (query, text_filter) = match terms {
+            Terms::Auto if query_is_intersection => (query, Some("ContainsAllTokens")),
+            Terms::Auto | Terms::TitleBody | Terms::Boosted | Terms::Vector | Terms::Hybrid => {
+                (query, None)
+            }
+            Terms::All => (query, Some("ContainsAllTokens")),
+            Terms::Phrase => (query.trim_matches('"'), Some("ContainsTokenSequence")),
+            Terms::Prefix => {
+                typed = typed_prefix(query);
+                (typed.as_str(), None)
+            }
+        };
+        let rank_by = match (sort, terms) {
+            (Some(Order::IdAscending), _) => serde_json::json!(["id", "asc"]),
+            (Some(Order::DocLengthAscending), _) => {
+                serde_json::json!([corpus::DOC_LENGTH_ATTRIBUTE, "asc"])
+            }
+            (Some(_), _) => serde_json::json!([corpus::DOC_LENGTH_ATTRIBUTE, "desc"]),
+            (None, Terms::Vector) => serde_json::json!(["vector", "ANN", options.vector(query)?]),
+            (None, Terms::TitleBody) => serde_json::json!([
+                "Sum",
+                [
+                    [corpus::TITLE_ATTRIBUTE, "BM25", query],
+                    ["text", "BM25", query],
+                ]
+            ]),
+            (None, Terms::Boosted) => serde_json::json!([
+                "Sum",
+                [
+                    [
+                        "Product",
+                        options.title_boost,
+                        [corpus::TITLE_ATTRIBUTE, "BM25", query],
+                    ],
+                    ["Product", options.text_boost, ["text", "BM25", query]],
+                ]
+            ]),
+            (None, Terms::Prefix) => {
+                serde_json::json!(["text", "BM25", query, {"last_as_prefix": true}])
+            }
+            (None, _) => serde_json::json!(["text", "BM25", query]),
+        };
+        if let Some(text_filter) = text_filter {
+            filters.push(serde_json::json!(["text", text_filter, query]));
+        }
+        // Without a BM25 ranking, the query terms only restrict the matching documents.
+        if (top_k == 0 || sort.is_some()) && text_filter.is_none() {
+            filters.push(serde_json::json!(["text", "ContainsAnyToken", query]));
+        }
+        let request_top_k = page_size.unwrap_or(top_k);
+        let mut body = if top_k == 0 {
+            match filters.as_slice() {
+                [filter] => serde_json::json!({
+                    "aggregate_by": {
+                        "count": ["Count"],
+                    },
+                    "filters": filter,
+                    "consistency": {"level": options.consistency.level()},
+                }),
+                _ => serde_json::json!({
+                    "aggregate_by": {
+                        "count": ["Count"],
+                    },
+                    "filters": ["And", filters],
+                    "consistency": {"level": options.consistency.level()},
+                }),
+            }
+        } else if let Terms::Hybrid = terms {
+            serde_json::json!({
+                "queries": [
+                    {
+                        "rank_by": rank_by,
+                        "top_k": top_k,
+                    },
+                    {
+                        "rank_by": ["vector", "ANN", options.vector(query)?],
+                        "top_k": top_k,
+                    },
+                ],
+                "consistency": {"level": options.consistency.level()},
+            })
+        } else {
+            match filters.as_slice() {
+                [] => serde_json::json!({
+                    "rank_by": rank_by,
+                    "top_k": request_top_k,
+                    "consistency": {"level": options.consistency.level()},
+                }),
+                [filter] => serde_json::json!({
+                    "rank_by": rank_by,
+                    "filters": filter,
+                    "top_k": request_top_k,
+                    "consistency": {"level": options.consistency.level()},
+                }),
+                _ => serde_json::json!({
+                    "rank_by": rank_by,
+                    "filters": ["And", filters],
+                    "top_k": request_top_k,
+                    "consistency": {"level": options.consistency.level()},
+                }),
+            }
+        };
+        if group_by {
+            body["group_by"] = serde_json::json!([corpus::GROUP_ATTRIBUTE]);
+            body["top_k"] = corpus::GROUPS.into();
+        }
+        let mut include_attributes = vec![];
+        if matches!(
+            sort,
+            Some(Order::DocLengthAscending | Order::DocLengthDescending)
+        ) {
+            // Needed to merge the rankings of several shards.
+            include_attributes.push(corpus::DOC_LENGTH_ATTRIBUTE);
+        }
+        if snippets {
+            include_attributes.push("text");
+        }
+        if !include_attributes.is_empty() {
+            body["include_attributes"] = include_attributes.into();
+        }
+        Some(Query {
+            command: command.to_string(),
+            query: written.to_string(),
+            count_only: top_k == 0,
+            grouped: group_by,
+            fused: matches!(terms, Terms::Hybrid),
+            top_k,
+            page_size,
+            cursor,
+            orders: match terms {
+                _ if top_k == 0 => vec![],
+                _ if sort.is_some() => vec![sort?],
+                Terms::Vector => vec![Order::Ascending],
+                Terms::Hybrid => vec![Order::Descending, Order::Ascending],
+                _ => vec![Order::Descending],
+            },
+            body,
+        })
+    }
+}
+
+/// A parsed command name.
+struct Command {
+    /// 0 for count queries.
+    top_k: usize,
+    page_size: Option<usize>,
+    cursor: bool,
+    filter: Option<Filter>,
+    terms: Terms,
+    /// Ranks the matching documents by an attribute instead of their score.
+    sort: Option<Order>,
+    /// Counts the matching documents per group.
+    group_by: bool,
+    /// Returns the text of the documents along with their ids.
+    snippets: bool,
+}
+
+impl Command {
+    /// Whether the command filters, sorts or groups by an attribute set by [`corpus::augment`].
+    fn uses_synthetic_attributes(&self) -> bool {
+        self.group_by
+            || matches!(
+                self.sort,
+                Some(Order::DocLengthAscending | Order::DocLengthDescending)
+            )
+            || matches!(
+                self.filter,
+                Some(Filter::Range(_) | Filter::Glob(_) | Filter::Regex(_))
+            )
+    }
+}
+
+/// Parses `[<terms>](TOP_<k>|PAGE_<size>x<pages>|REFETCH_<size>x<pages>|COUNT)[<filter>][<sort>]`,
+/// where `<terms>` is one of `INTERSECTION_`, `PHRASE_`, `PREFIX_`, `TITLE_BODY_`, `BOOSTED_`,
+/// `ANN_` and `HYBRID_`, `<filter>` is one of `_FILTER_<pct>%`, `_FILTER_<a>%_AND_<b>%`,
+/// `_FILTER_<a>%_OR_<b>%`, `_NOT_FILTER_<pct>%`, `_RANGE_<pct>%`, `_GLOB_<pct>%` and
+/// `_REGEX_<pct>%`, and `<sort>` is one of `_SORT_DOC_LENGTH_ASC`, `_SORT_DOC_LENGTH_DESC` and
+/// `_SORT_ID` for top-k commands, and `_GROUP_BY` for count commands. Top-k commands ending in
+/// `_SNIPPETS` also fetch the text of the documents.
+///
+/// `PAGE_*` commands page with an id cursor and require `_SORT_ID`; `REFETCH_*` commands page
+/// any ranking by fetching the rows of the previous pages again. `PREFIX_*`, `TITLE_BODY_*` and
+/// `BOOSTED_*` commands only support top-k commands without a sort. `ANN_*` and `HYBRID_*`
+/// commands only support `TOP_<k>` without a filter or sort,
+/// `HYBRID_*` commands don't support snippets, tag filters only support the given
+/// `selectivities`, and pattern filters only support the selectivities of
+/// [`corpus::code_prefix`].
+fn parse_command(command: &str, selectivities: &[f64]) -> Option<Command> {
+    let (command, snippets) = match command.strip_suffix("_SNIPPETS") {
+        Some(command) => (command, true),
+        None => (command, false),
+    };
+    let (command, group_by) = match command.strip_suffix("_GROUP_BY") {
+        Some(command) => (command, true),
+        None => (command, false),
+    };
+    let (command, sort) = [
+        ("_SORT_DOC_LENGTH_ASC", Order::DocLengthAscending),
+        ("_SORT_DOC_LENGTH_DESC", Order::DocLengthDescending),
+        ("_SORT_ID", Order::IdAscending),
+    ]
+    .into_iter()
+    .find_map(|(suffix, order)| Some((command.strip_suffix(suffix)?, Some(order))))
+    .unwrap_or((command, None));
+    let (terms, rest) = [
+        ("INTERSECTION_", Terms::All),
+        ("PHRASE_", Terms::Phrase),
+        ("PREFIX_", Terms::Prefix),
+        ("TITLE_BODY_", Terms::TitleBody),
+        ("BOOSTED_", Terms::Boosted),
+        ("ANN_", Terms::Vector),
+        ("HYBRID_", Terms::Hybrid),
+    ]
+    .into_iter()
+    .find_map(|(prefix, terms)| Some((terms, command.strip_prefix(prefix)?)))
+    .unwrap_or((Terms::Auto, command));
+    let (top_k, page_size, cursor, rest) = if let Some(rest) = rest.strip_prefix("COUNT") {
+        (0, None, false, rest)
+    } else if let Some(rest) = rest.strip_prefix("PAGE_") {
+        let (page_size, pages, rest) = parse_pages(rest)?;
+        (page_size * pages, Some(page_size), true, rest)
+    } else if let Some(rest) = rest.strip_prefix("REFETCH_") {
+        let (page_size, pages, rest) = parse_pages(rest)?;
+        (page_size * pages, Some(page_size), false, rest)
+    } else {
+        let (top_k, rest) = parse_number(rest.strip_prefix("TOP_")?)?;
+        (top_k, None, false, rest)
+    };
+    let filter = if rest.is_empty() {
+        None
+    } else if let Some(tag) = rest.strip_prefix("_NOT_FILTER_") {
+        Some(Filter::NotTag(parse_tag(tag, selectivities)?))
+    } else if let Some(percent) = rest.strip_prefix("_RANGE_") {
+        Some(Filter::Range(parse_percent(percent)?))
+    } else if let Some(percent) = rest.strip_prefix("_GLOB_") {
+        Some(Filter::Glob(corpus::code_prefix(parse_percent(percent)?)?))
+    } else if let Some(percent) = rest.strip_prefix("_REGEX_") {
+        Some(Filter::Regex(corpus::code_prefix(parse_percent(percent)?)?))
+    } else {
+        let tags = rest.strip_prefix("_FILTER_")?;
+        Some(if let Some((a, b)) = tags.split_once("_AND_") {
+            Filter::And(vec![
+                Filter::Tag(parse_tag(a, selectivities)?),
+                Filter::Tag(parse_tag(b, selectivities)?),
+            ])
+        } else if let Some((a, b)) = tags.split_once("_OR_") {
+            Filter::Or(vec![
+                Filter::Tag(parse_tag(a, selectivities)?),
+                Filter::Tag(parse_tag(b, selectivities)?),
+            ])
+        } else {
+            Filter::Tag(parse_tag(tags, selectivities)?)
+        })
+    };
+    if matches!(terms, Terms::Vector | Terms::Hybrid)
+        && (top_k == 0 || page_size.is_some() || filter.is_some() || sort.is_some())
+    {
+        return None;
+    }
+    if matches!(terms, Terms::Prefix | Terms::TitleBody | Terms::Boosted)
+        && (top_k == 0 || sort.is_some())
+    {
+        return None;
+    }
+    if (top_k == 0 && (sort.is_some() || snippets)) || (top_k > 0 && group_by) {
+        return None;
+    }
+    if matches!(terms, Terms::Hybrid) && snippets {
+        return None;
+    }
+    // Ids are the only order with a cursor.
+    if cursor && !matches!(sort, Some(Order::IdAscending)) {
+        return None;
+    }
+    Some(Command {
+        top_k,
+        page_size,
+        cursor,
+        filter,
+        terms,
+        sort,
+        group_by,
+        snippets,
+    })
+}
+
+/// Parses the positive number at the start of `value` and returns it with the rest of `value`.
+fn parse_number(value: &str) -> Option<(usize, &str)> {
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let number = value[..digits].parse().ok().filter(|&number| number > 0)?;
+    Some((number, &value[digits..]))
+}
+
+/// Parses the `<size>x<pages>` at the start of `value` and returns them with the rest of `value`.
+fn parse_pages(value: &str) -> Option<(usize, usize, &str)> {
+    let (page_size, rest) = value.split_once('x')?;
+    let page_size = page_size.parse().ok().filter(|&size| size > 0)?;
+    let (pages, rest) = parse_number(rest)?;
+    Some((page_size, pages, rest))
+}
+
+/// Parses a `<pct>%` selectivity, e.g. `5%` or `0.1%`.
+fn parse_percent(percent: &str) -> Option<f64> {
+    let percent: f64 = percent.strip_suffix('%')?.parse().ok()?;
+    (0.0..=100.0).contains(&percent).then_some(percent)
+}
+
+/// Parses a `<pct>%` selectivity, one of `selectivities`, into the `filter` tag carrying it.
+fn parse_tag(tag: &str, selectivities: &[f64]) -> Option<String> {
+    let percent = parse_percent(tag)?;
+    selectivities
+        .contains(&percent)
+        .then(|| corpus::filter_tag(percent))
+}
+
+/// The query as typed so far in a search box: without quotes and `+` operators, and with the last
+/// term cut to the first half of its characters, rounded up.
+fn typed_prefix(query: &str) -> String {
+    let terms = query
+        .split_whitespace()
+        .map(|term| term.trim_matches(|c| c == '"' || c == '+'))
+        .filter(|term| !term.is_empty())
+        .collect::<Vec<_>>();
+    let Some((last, rest)) = terms.split_last() else {
+        return String::new();
+    };
+    let typed = last.chars().count().div_ceil(2);
+    let mut prefix = rest.join(" ");
+    if !prefix.is_empty() {
+        prefix.push(' ');
+    }
+    prefix.extend(last.chars().take(typed));
+    prefix
+}
+
+/// Restricts the documents matched by the query terms.
+enum Filter {
+    /// Documents whose `filter` tags contain the given tag.
+    Tag(String),
+    /// Documents whose `filter` tags don't contain the given tag.
+    NotTag(String),
+    /// Documents whose synthetic timestamp falls in a window matching the given percentage of
+    /// the corpus.
+    Range(f64),
+    /// Documents whose [`corpus::CODE_ATTRIBUTE`] starts with the given digits, matched with a
+    /// glob pattern.
+    Glob(String),
+    /// Documents whose [`corpus::CODE_ATTRIBUTE`] starts with the given digits, matched with a
+    /// regular expression.
+    Regex(String),
+    /// Documents matching all of the given filters.
+    And(Vec<Filter>),
+    /// Documents matching any of the given filters.
+    Or(Vec<Filter>),
+}
+
+impl Filter {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Filter::Tag(tag) => serde_json::json!(["filter", "Contains", tag]),
+            Filter::NotTag(tag) => serde_json::json!(["filter", "NotContains", tag]),
+            Filter::Range(percent) => {
+                let (start, end) = corpus::timestamp_window(*percent);
+                serde_json::json!([
+                    "And",
+                    [
+                        [corpus::TIMESTAMP_ATTRIBUTE, "Gte", start],
+                        [corpus::TIMESTAMP_ATTRIBUTE, "Lt", end],
+                    ]
+                ])
+            }
+            Filter::Glob(prefix) => {
+                serde_json::json!([corpus::CODE_ATTRIBUTE, "Glob", format!("{prefix}*")])
+            }
+            Filter::Regex(prefix) => {
+                serde_json::json!([corpus::CODE_ATTRIBUTE, "Regex", format!("^{prefix}")])
+            }
+            Filter::And(filters) => serde_json::json!([
+                "And",
+                filters
+                    .iter()
+                    .map(|filter| filter.to_json())
+                    .collect::<Vec<_>>(),
+            ]),
+            Filter::Or(filters) => serde_json::json!([
+                "Or",
+                filters
+                    .iter()
+                    .map(|filter| filter.to_json())
+                    .collect::<Vec<_>>(),
+            ]),
+        }
+    }
+}
+
+/// How the query terms restrict the set of matching documents.
+#[derive(Clone, Copy)]
+enum Terms {
+    /// Documents matching any term, or all terms if the query is written as `+a +b`.
+    Auto,
+    /// Documents matching all terms.
+    All,
+    /// Documents containing the terms as a consecutive sequence.
+    Phrase,
+    /// Documents matching any term of the [`typed_prefix`] of the query, the last one as a prefix.
+    Prefix,
+    /// Documents matching any term in the text or the title, ranked by the sum of the BM25 scores
+    /// of both attributes.
+    TitleBody,
+    /// Like [`Terms::TitleBody`], with the scores of the attributes weighted by
+    /// [`ParseOptions::title_boost`] and [`ParseOptions::text_boost`].
+    Boosted,
+    /// All documents, ranked by the distance of their vector to the query's embedding.
+    Vector,
+    /// All documents, ranked by fusing the BM25 and the vector distance rankings.
+    Hybrid,
+}
+
+/// How rows are ordered.
+#[derive(Clone, Copy)]
+pub enum Order {
+    /// By decreasing `$dist` score, e.g. BM25.
+    Descending,
+    /// By increasing `$dist` distance, e.g. vector distance.
+    Ascending,
+    /// By increasing [`corpus::DOC_LENGTH_ATTRIBUTE`].
+    DocLengthAscending,
+    /// By decreasing [`corpus::DOC_LENGTH_ATTRIBUTE`].
+    DocLengthDescending,
+    /// By increasing id, the cheapest order to rank by.
+    IdAscending,
+}
+
+/// Merges the top-k rankings of several shards into the global top-k.
+pub fn merge_rankings(rankings: Vec<Vec<Row>>, top_k: usize, order: Order) -> Vec<Row> {
+    let mut rows = rankings.into_iter().flatten().collect::<Vec<_>>();
+    rows.sort_by(|a, b| {
+        let dist = |row: &Row| row.dist.unwrap_or(0.0);
+        let doc_length = |row: &Row| row.doc_length.unwrap_or(0);
+        match order {
+            Order::Descending => dist(b).total_cmp(&dist(a)),
+            Order::Ascending => dist(a).total_cmp(&dist(b)),
+            Order::DocLengthAscending => doc_length(a).cmp(&doc_length(b)),
+            Order::DocLengthDescending => doc_length(b).cmp(&doc_length(a)),
+            // Ids are strings, see the schema of `build_index`.
+            Order::IdAscending => a.id_string().cmp(&b.id_string()),
+        }
+    });
+    rows.truncate(top_k);
+    rows
+}
+
+/// The usual RRF constant, damping the influence of the top ranks.
+const RRF_K: f64 = 60.0;
+
+/// Fuses `rankings` with reciprocal rank fusion and returns the ids of the `top_k` best
+/// documents, best first.
+///
+/// Every document scores `sum(1 / (RRF_K + rank))` over the rankings it appears in, with ranks
+/// starting at 1. Ties are broken by id to keep the output deterministic.
+pub fn reciprocal_rank_fusion(rankings: &[Vec<Row>], top_k: usize) -> Vec<String> {
+    let mut scores: HashMap<String, f64> = HashMap::new();
+    for ranking in rankings {
+        for (rank, row) in ranking.iter().enumerate() {
+            *scores.entry(row.id_string()).or_default() += 1.0 / (RRF_K + rank as f64 + 1.0);
+        }
+    }
+    let mut fused = scores.into_iter().collect::<Vec<_>>();
+    fused.sort_by(|(a_id, a_score), (b_id, b_score)| {
+        b_score.total_cmp(a_score).then_with(|| a_id.cmp(b_id))
+    });
+    fused.truncate(top_k);
+    fused.into_iter().map(|(id, _)| id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn parse(command: &str, query: &str) -> Option<Query> {
+        Query::parse(command, query, &ParseOptions::default())
+    }
+
+    fn body(command: &str, query: &str) -> serde_json::Value {
+        parse(command, query)
+            .unwrap_or_else(|| panic!("{command} should be supported"))
+            .body
+    }
+
+    #[test]
+    fn top_k() {
+        let query = parse("TOP_10", "the who").unwrap();
+        assert_eq!(query.top_k, 10);
+        assert!(!query.count_only);
+        assert!(matches!(query.orders.as_slice(), [Order::Descending]));
+        assert_eq!(
+            query.body,
+            json!({
+                "rank_by": ["text", "BM25", "the who"],
+                "top_k": 10,
+                "consistency": {"level": "eventual"},
+            })
+        );
+    }
+
+    #[test]
+    fn tag_filters() {
+        assert_eq!(
+            body("TOP_10_FILTER_20%", "the")["filters"],
+            json!(["filter", "Contains", "20%"])
+        );
+        assert_eq!(
+            body("TOP_10_NOT_FILTER_80%", "the")["filters"],
+            json!(["filter", "NotContains", "80%"])
+        );
+        assert_eq!(
+            body("COUNT_FILTER_5%_AND_20%", "the")["filters"],
+            json!([
+                "And",
+                [
+                    [
+                        "And",
+                        [["filter", "Contains", "5%"], ["filter", "Contains", "20%"]]
+                    ],
+                    ["text", "ContainsAnyToken", "the"],
+                ]
+            ])
+        );
+        assert_eq!(
+            body("TOP_10_FILTER_5%_OR_80%", "the")["filters"],
+            json!([
+                "Or",
+                [["filter", "Contains", "5%"], ["filter", "Contains", "80%"]]
+            ])
+        );
+        // Only the selectivities of the namespace are supported.
+        assert!(parse("TOP_10_FILTER_15%", "the").is_none());
+        let options = ParseOptions {
+            filter_selectivities: vec![15.0, 0.1],
+            ..ParseOptions::default()
+        };
+        let query = Query::parse("TOP_10_FILTER_0.1%", "the", &options).unwrap();
+        assert_eq!(query.body["filters"], json!(["filter", "Contains", "0.1%"]));
+        assert!(Query::parse("TOP_10_FILTER_20%", "the", &options).is_none());
+    }
+
+    #[test]
+    fn top_k_override() {
+        let options = ParseOptions {
+            top_k_override: Some(100),
+            ..ParseOptions::default()
+        };
+        let query = Query::parse("TOP_10", "the", &options).unwrap();
+        assert_eq!(query.top_k, 100);
+        assert_eq!(query.body["top_k"], json!(100));
+        // Counts keep their own size.
+        assert!(Query::parse("COUNT", "the", &options).unwrap().count_only);
+    }
+
+    #[test]
+    fn unsupported() {
+        for command in [
+            "",
+            "TOP",
+            "TOP_0",
+            "TOP_10_",
+            "TOP_10_FILTER",
+            "SEARCH",
+            "top_10",
+        ] {
+            assert!(parse(command, "the").is_none(), "{command}");
+        }
+    }
+
+    #[test]
+    fn phrase() {
+        let top_k = body("PHRASE_TOP_10", "\"new york\"");
+        assert_eq!(top_k["rank_by"], json!(["text", "BM25", "new york"]));
+        assert_eq!(
+            top_k["filters"],
+            json!(["text", "ContainsTokenSequence", "new york"])
+        );
+        assert_eq!(
+            body("PHRASE_COUNT", "\"new york\"")["filters"],
+            json!(["text", "ContainsTokenSequence", "new york"])
+        );
+    }
+
+    #[test]
+    fn intersection() {
+        // Queries written `+a +b` require all terms, like INTERSECTION_* commands.
+        assert_eq!(
+            body("TOP_10", "+the +who")["filters"],
+            json!(["text", "ContainsAllTokens", "+the +who"])
+        );
+        assert_eq!(
+            body("INTERSECTION_TOP_10", "the who")["filters"],
+            json!(["text", "ContainsAllTokens", "the who"])
+        );
+    }
+
+    #[test]
+    fn count() {
+        let query = parse("COUNT", "the who").unwrap();
+        assert!(query.count_only);
+        assert!(query.orders.is_empty());
+        assert_eq!(
+            query.body,
+            json!({
+                "aggregate_by": {"count": ["Count"]},
+                "filters": ["text", "ContainsAnyToken", "the who"],
+                "consistency": {"level": "eventual"},
+            })
+        );
+        assert_eq!(
+            body("INTERSECTION_COUNT", "the who")["filters"],
+            json!(["text", "ContainsAllTokens", "the who"])
+        );
+    }
+
+    #[test]
+    fn range_filters() {
+        // Like the other attribute commands, only with the synthetic attributes.
+        assert!(parse("TOP_10_RANGE_10%", "the").is_none());
+        let options = ParseOptions {
+            synthetic_attributes: true,
+            ..ParseOptions::default()
+        };
+        let query = Query::parse("TOP_10_RANGE_10%", "the", &options).unwrap();
+        let (start, end) = corpus::timestamp_window(10.0);
+        assert_eq!(
+            query.body["filters"],
+            json!([
+                "And",
+                [["timestamp", "Gte", start], ["timestamp", "Lt", end]]
+            ])
+        );
+        assert!(Query::parse("TOP_10_RANGE_101%", "the", &options).is_none());
+    }
+
+    #[test]
+    fn vectors() {
+        // Vector commands need an embedding of the query.
+        assert!(parse("ANN_TOP_10", "the").is_none());
+        let vectors = QueryVectors(HashMap::from([("the".to_string(), vec![0.5, 1.0])]));
+        let options = ParseOptions {
+            vectors: Some(vectors),
+            ..ParseOptions::default()
+        };
+        let query = Query::parse("ANN_TOP_10", "the", &options).unwrap();
+        assert_eq!(query.body["rank_by"], json!(["vector", "ANN", [0.5, 1.0]]));
+        assert!(matches!(query.orders.as_slice(), [Order::Ascending]));
+        assert!(Query::parse("ANN_TOP_10", "who", &options).is_none());
+        assert!(Query::parse("ANN_TOP_10_FILTER_20%", "the", &options).is_none());
+        assert!(Query::parse("ANN_COUNT", "the", &options).is_none());
+    }
+
+    #[test]
+    fn hybrid() {
+        let vectors = QueryVectors(HashMap::from([("the".to_string(), vec![0.5, 1.0])]));
+        let options = ParseOptions {
+            vectors: Some(vectors),
+            ..ParseOptions::default()
+        };
+        let query = Query::parse("HYBRID_TOP_10", "the", &options).unwrap();
+        assert!(query.fused);
+        assert!(matches!(
+            query.orders.as_slice(),
+            [Order::Descending, Order::Ascending]
+        ));
+        let queries = query.body["queries"].as_array().unwrap();
+        assert_eq!(queries[0]["rank_by"], json!(["text", "BM25", "the"]));
+        assert_eq!(queries[1]["rank_by"], json!(["vector", "ANN", [0.5, 1.0]]));
+        assert!(Query::parse("HYBRID_TOP_10_SNIPPETS", "the", &options).is_none());
+        assert!(parse("HYBRID_TOP_10", "the").is_none());
+    }
+
+    #[test]
+    fn consistency() {
+        assert_eq!(
+            body("TOP_10", "the")["consistency"],
+            json!({"level": "eventual"})
+        );
+        let options = ParseOptions {
+            consistency: Consistency::Strong,
+            ..ParseOptions::default()
+        };
+        for command in ["TOP_10", "COUNT", "TOP_10_FILTER_20%"] {
+            let query = Query::parse(command, "the", &options).unwrap();
+            assert_eq!(query.body["consistency"], json!({"level": "strong"}));
+        }
+    }
+
+    #[test]
+    fn pages() {
+        let query = parse("PAGE_10x3_SORT_ID", "the").unwrap();
+        assert_eq!(query.top_k, 30);
+        assert_eq!(query.page_size, Some(10));
+        assert!(query.cursor);
+        assert_eq!(query.body["top_k"], json!(10));
+        // Only ids have a cursor.
+        assert!(parse("PAGE_10x3", "the").is_none());
+        let query = parse("REFETCH_10x3", "the").unwrap();
+        assert_eq!(query.top_k, 30);
+        assert_eq!(query.page_size, Some(10));
+        assert!(!query.cursor);
+        assert!(parse("REFETCH_0x3", "the").is_none());
+        assert!(parse("REFETCH_10x0", "the").is_none());
+        // Pages keep their own size.
+        let options = ParseOptions {
+            top_k_override: Some(100),
+            ..ParseOptions::default()
+        };
+        let query = Query::parse("REFETCH_10x3", "the", &options).unwrap();
+        assert_eq!(query.top_k, 30);
+    }
+
+    #[test]
+    fn sorts() {
+        let query = parse("TOP_10_SORT_ID", "the who").unwrap();
+        assert!(matches!(query.orders.as_slice(), [Order::IdAscending]));
+        assert_eq!(query.body["rank_by"], json!(["id", "asc"]));
+        // Without a BM25 ranking, the terms become a filter.
+        assert_eq!(
+            query.body["filters"],
+            json!(["text", "ContainsAnyToken", "the who"])
+        );
+        assert!(parse("COUNT_SORT_ID", "the").is_none());
+        assert!(parse("TOP_10_SORT_DOC_LENGTH_DESC", "the").is_none());
+        let options = ParseOptions {
+            synthetic_attributes: true,
+            ..ParseOptions::default()
+        };
+        let query = Query::parse("TOP_10_SORT_DOC_LENGTH_DESC", "the", &options).unwrap();
+        assert!(matches!(
+            query.orders.as_slice(),
+            [Order::DocLengthDescending]
+        ));
+        assert_eq!(query.body["rank_by"], json!(["doc_length", "desc"]));
+        assert_eq!(query.body["include_attributes"], json!(["doc_length"]));
+    }
+
+    #[test]
+    fn group_by() {
+        assert!(parse("COUNT_GROUP_BY", "the").is_none());
+        let options = ParseOptions {
+            synthetic_attributes: true,
+            ..ParseOptions::default()
+        };
+        let query = Query::parse("COUNT_GROUP_BY", "the", &options).unwrap();
+        assert!(query.grouped);
+        assert!(query.count_only);
+        assert_eq!(query.body["group_by"], json!(["group"]));
+        assert_eq!(query.body["top_k"], json!(corpus::GROUPS));
+        assert!(Query::parse("TOP_10_GROUP_BY", "the", &options).is_none());
+    }
+
+    #[test]
+    fn snippets() {
+        assert_eq!(
+            body("TOP_10_SNIPPETS", "the")["include_attributes"],
+            json!(["text"])
+        );
+        assert_eq!(body("TOP_10", "the").get("include_attributes"), None);
+        assert!(parse("COUNT_SNIPPETS", "the").is_none());
+    }
+
+    #[test]
+    fn boosted() {
+        assert_eq!(
+            body("BOOSTED_TOP_10", "the")["rank_by"],
+            json!([
+                "Sum",
+                [
+                    ["Product", 2.0, ["title", "BM25", "the"]],
+                    ["Product", 1.0, ["text", "BM25", "the"]],
+                ]
+            ])
+        );
+        let options = ParseOptions {
+            title_boost: 3.0,
+            text_boost: 0.5,
+            ..ParseOptions::default()
+        };
+        let query = Query::parse("BOOSTED_TOP_10", "the", &options).unwrap();
+        assert_eq!(query.body["rank_by"][1][0][1], json!(3.0));
+        assert_eq!(query.body["rank_by"][1][1][1], json!(0.5));
+        assert!(parse("BOOSTED_COUNT", "the").is_none());
+        assert!(parse("BOOSTED_TOP_10_SORT_ID", "the").is_none());
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!(typed_prefix("+new +york"), "new yo");
+        assert_eq!(typed_prefix("\"a\""), "a");
+        assert_eq!(typed_prefix(""), "");
+        assert_eq!(
+            body("PREFIX_TOP_10", "new york")["rank_by"],
+            json!(["text", "BM25", "new yo", {"last_as_prefix": true}])
+        );
+        assert!(parse("PREFIX_COUNT", "new york").is_none());
+    }
+
+    #[test]
+    fn pattern_filters() {
+        assert!(parse("TOP_10_GLOB_1%", "the").is_none());
+        let options = ParseOptions {
+            synthetic_attributes: true,
+            ..ParseOptions::default()
+        };
+        let filters =
+            |command| Query::parse(command, "the", &options).unwrap().body["filters"].take();
+        assert_eq!(filters("TOP_10_GLOB_1%"), json!(["code", "Glob", "00*"]));
+        assert_eq!(filters("TOP_10_REGEX_10%"), json!(["code", "Regex", "^0"]));
+        // Pattern filters only support powers of 10.
+        assert!(Query::parse("TOP_10_GLOB_3%", "the", &options).is_none());
+    }
+}
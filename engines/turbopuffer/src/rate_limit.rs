@@ -0,0 +1,100 @@
+//! Client-side rate limiting, to keep the benchmark from overwhelming shared deployments.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A token bucket refilled at `rate` tokens per second, holding at most one second worth of
+/// tokens.
+///
+/// Acquiring more tokens than available puts the bucket in debt, so that a single large request
+/// is let through and the ones after it wait until the debt is paid off.
+pub struct RateLimiter {
+    rate: f64,
+    state: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64) -> RateLimiter {
+        RateLimiter {
+            rate,
+            state: Mutex::new(Bucket {
+                tokens: rate,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes `tokens` from the bucket, waiting first if it is in debt.
+    pub async fn acquire(&self, tokens: f64) {
+        let wait = {
+            let mut bucket = self.state.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * self.rate;
+            bucket.tokens = (bucket.tokens + refill).min(self.rate);
+            bucket.refilled_at = now;
+            let wait = if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / self.rate)
+            } else {
+                Duration::ZERO
+            };
+            bucket.tokens -= tokens;
+            wait
+        };
+        // Even a zero sleep waits for the next tick of the timer, about a millisecond.
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn burst_passes() {
+        let limiter = RateLimiter::new(100.0);
+        let start = Instant::now();
+        for _ in 0..100 {
+            limiter.acquire(1.0).await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn debt_is_paid_off() {
+        let limiter = RateLimiter::new(100.0);
+        // Empties the bucket, then puts it 50 tokens, half a second, in debt.
+        limiter.acquire(100.0).await;
+        let start = Instant::now();
+        limiter.acquire(50.0).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+        limiter.acquire(1.0).await;
+        let waited = start.elapsed();
+        assert!(
+            waited >= Duration::from_millis(450) && waited < Duration::from_millis(1000),
+            "waited {waited:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn sustained_rate() {
+        let limiter = RateLimiter::new(200.0);
+        limiter.acquire(200.0).await;
+        let start = Instant::now();
+        // 100 tokens past the initial burst take half a second.
+        for _ in 0..=100 {
+            limiter.acquire(1.0).await;
+        }
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(450) && elapsed < Duration::from_millis(1000),
+            "took {elapsed:?}"
+        );
+    }
+}
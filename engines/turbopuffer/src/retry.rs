@@ -0,0 +1,56 @@
+use std::time::{Duration, Instant};
+
+use crate::metrics::METRICS;
+
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Sends the request built by `build`, retrying transient failures (429, 5xx and connection
+/// errors) with exponential backoff and full jitter. The final response or error is returned once
+/// `max_attempts` is exhausted, together with the number of attempts made.
+pub async fn send_with_retry(
+    max_attempts: u32,
+    build: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<(reqwest::Response, u32), anyhow::Error> {
+    let mut attempt = 1;
+    loop {
+        METRICS.request_started();
+        let start = Instant::now();
+        let result = build().send().await;
+        let failed = match &result {
+            Ok(response) => !response.status().is_success(),
+            Err(_) => true,
+        };
+        METRICS.request_finished(start.elapsed(), failed);
+        let retryable = match &result {
+            Ok(response) => {
+                response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || response.status().is_server_error()
+            }
+            Err(err) => err.is_connect() || err.is_timeout(),
+        };
+        if !retryable || attempt >= max_attempts {
+            return Ok((result?.error_for_status()?, attempt));
+        }
+        let delay = backoff(attempt);
+        match &result {
+            Ok(response) => eprintln!(
+                "request failed with {} (attempt {attempt}/{max_attempts}), retrying in {delay:?}",
+                response.status()
+            ),
+            Err(err) => eprintln!(
+                "request failed: {err} (attempt {attempt}/{max_attempts}), retrying in {delay:?}"
+            ),
+        }
+        METRICS.request_retried();
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    let cap = RETRY_BASE_DELAY
+        .saturating_mul(1 << (attempt - 1).min(16))
+        .min(RETRY_MAX_DELAY);
+    cap.mul_f64(rand::random::<f64>())
+}
@@ -0,0 +1,209 @@
+use std::path::Path;
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+
+/// Records request latencies with microsecond precision.
+pub struct LatencyHistogram {
+    histogram: Histogram<u64>,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            // 1µs to 1h with three significant digits; slower requests are clamped to 1h.
+            histogram: Histogram::new_with_bounds(1, 3_600_000_000, 3).unwrap(),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        self.histogram
+            .saturating_record(latency.as_micros().try_into().unwrap_or(u64::MAX));
+    }
+
+    /// Adds the latencies recorded by `other`.
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        self.histogram
+            .add(&other.histogram)
+            .expect("latency histograms have the same bounds");
+    }
+
+    pub fn len(&self) -> u64 {
+        self.histogram.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.histogram.is_empty()
+    }
+
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        Duration::from_micros(self.histogram.value_at_percentile(percentile))
+    }
+
+    pub fn mean(&self) -> Duration {
+        Duration::from_secs_f64(self.histogram.mean() / 1e6)
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_micros(self.histogram.max())
+    }
+
+    /// One line summary with the count, p50, p90, p99 and max latency.
+    pub fn summary(&self) -> String {
+        format!(
+            "count={} p50={:?} p90={:?} p99={:?} max={:?}",
+            self.len(),
+            self.percentile(50.0),
+            self.percentile(90.0),
+            self.percentile(99.0),
+            self.max(),
+        )
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let buckets: Vec<_> = self
+            .histogram
+            .iter_recorded()
+            .map(|bucket| {
+                serde_json::json!({
+                    "latency_us": bucket.value_iterated_to(),
+                    "count": bucket.count_at_value(),
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "count": self.len(),
+            "p50_us": self.histogram.value_at_percentile(50.0),
+            "p90_us": self.histogram.value_at_percentile(90.0),
+            "p99_us": self.histogram.value_at_percentile(99.0),
+            "max_us": self.histogram.max(),
+            "histogram": buckets,
+        })
+    }
+
+    pub fn write_json(&self, path: &Path) -> Result<(), anyhow::Error> {
+        std::fs::write(path, serde_json::to_vec_pretty(&self.to_json())?)?;
+        Ok(())
+    }
+}
+
+/// Two-sided p-value of the Mann-Whitney U test of `a` and `b` having the same distribution,
+/// with the normal approximation and the tie correction, which are accurate from about 20
+/// samples each. 1 if either sample is empty.
+pub fn mann_whitney_p(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    let mut values: Vec<(f64, bool)> = a
+        .iter()
+        .map(|&x| (x, true))
+        .chain(b.iter().map(|&x| (x, false)))
+        .collect();
+    values.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    // Tied values get the mean of their ranks.
+    let mut rank_sum = 0.0;
+    let mut tie_term = 0.0;
+    let mut i = 0;
+    while i < values.len() {
+        let mut j = i;
+        while j < values.len() && values[j].0 == values[i].0 {
+            j += 1;
+        }
+        let rank = (i + j + 1) as f64 / 2.0;
+        rank_sum += rank * values[i..j].iter().filter(|(_, in_a)| *in_a).count() as f64;
+        let ties = (j - i) as f64;
+        tie_term += ties * ties * ties - ties;
+        i = j;
+    }
+
+    let n = n1 + n2;
+    let u = rank_sum - n1 * (n1 + 1.0) / 2.0;
+    let mean = n1 * n2 / 2.0;
+    let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_term / (n * (n - 1.0)));
+    if variance <= 0.0 {
+        return 1.0;
+    }
+    // With a continuity correction.
+    let z = ((u - mean).abs() - 0.5).max(0.0) / variance.sqrt();
+    erfc(z / std::f64::consts::SQRT_2)
+}
+
+/// The complementary error function, with a relative error below 1.2e-7 (Numerical Recipes).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let polynomial = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * polynomial.exp();
+    if x >= 0.0 { result } else { 2.0 - result }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn erfc_values() {
+        assert_close(erfc(0.0), 1.0, 1e-7);
+        assert_close(erfc(0.5), 0.479_500_122, 1e-7);
+        assert_close(erfc(1.0), 0.157_299_207, 1e-7);
+        assert_close(erfc(2.0), 0.004_677_735, 1e-7);
+        assert_close(erfc(-1.0), 1.842_700_793, 1e-7);
+        assert_close(erfc(10.0), 0.0, 1e-7);
+    }
+
+    #[test]
+    fn separated_samples() {
+        // U = 0, z = (12.5 - 0.5) / sqrt(25 * 11 / 12).
+        let a = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let b = [6.0, 7.0, 8.0, 9.0, 10.0];
+        assert_close(mann_whitney_p(&a, &b), 0.012_185, 1e-5);
+        assert_close(mann_whitney_p(&b, &a), 0.012_185, 1e-5);
+
+        let a = (0..30).map(f64::from).collect::<Vec<_>>();
+        let b = (100..130).map(f64::from).collect::<Vec<_>>();
+        assert!(mann_whitney_p(&a, &b) < 1e-9);
+    }
+
+    #[test]
+    fn same_samples() {
+        let a = (0..50).map(f64::from).collect::<Vec<_>>();
+        assert_close(mann_whitney_p(&a, &a), 1.0, 1e-7);
+        // Interleaved samples differ by a shift of less than a rank.
+        let b = a.iter().map(|x| x + 0.5).collect::<Vec<_>>();
+        assert!(mann_whitney_p(&a, &b) > 0.5);
+    }
+
+    #[test]
+    fn ties() {
+        // U = 2, and the tie correction shrinks the variance from 12 to 8.57.
+        let a = [1.0, 1.0, 1.0, 2.0];
+        let b = [2.0, 2.0, 2.0, 2.0];
+        assert_close(mann_whitney_p(&a, &b), 0.060_298, 1e-5);
+        // No variance at all: nothing to tell the samples apart.
+        assert_eq!(mann_whitney_p(&[3.0; 10], &[3.0; 10]), 1.0);
+    }
+
+    #[test]
+    fn empty_sample() {
+        assert_eq!(mann_whitney_p(&[], &[1.0, 2.0]), 1.0);
+        assert_eq!(mann_whitney_p(&[1.0], &[]), 1.0);
+    }
+}
@@ -8,6 +8,9 @@ from collections import defaultdict
 
 COMMANDS = os.environ['COMMANDS'].split(' ')
 
+# Returned by SearchClient.query for queries the engine aborted after its query timeout.
+TIMEOUT = object()
+
 class SearchClient:
 
     def __init__(self, engine):
@@ -29,6 +32,8 @@ class SearchClient:
         recv = self.process.stdout.readline().strip()
         if recv == b"UNSUPPORTED":
             return None
+        if recv == b"TIMEOUT":
+            return TIMEOUT
         cnt = int(recv)
         return cnt
 
@@ -108,7 +113,8 @@ if __name__ == "__main__":
                     "query": query.query,
                     "tags": query.tags,
                     "count": 0,
-                    "duration": []
+                    "duration": [],
+                    "timeouts": 0
                 }
                 query_idx[query.query] = query_result
                 engine_results.append(query_result)
@@ -130,8 +136,13 @@ if __name__ == "__main__":
             printProgressBar(0, prefix = 'Run:   ', suffix = 'Complete', length = 50)
             for i in range(NUM_ITER):
                 for (query, count, duration) in drive(queries_shuffled, search_client, command):
-                    if count is None:
-                        query_idx[query.query] = {count: -1, duration: []}
+                    if count is TIMEOUT:
+                        # Timeouts are intermittent: count them, and keep the count and durations
+                        # of the iterations that succeeded. The time until the engine gave up is
+                        # not a latency.
+                        query_idx[query.query]["timeouts"] += 1
+                    elif count is None:
+                        query_idx[query.query] = {"count": -1, "duration": []}
                     else:
                         query_idx[query.query]["count"] = count
                         query_idx[query.query]["duration"].append(duration)